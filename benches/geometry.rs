@@ -0,0 +1,76 @@
+//! Output size and render time of a large notebook with each geometry pipeline.
+//!
+//! Run with `cargo bench --bench geometry`, pages are generated as sampled by the tablet,
+//! a point every few tenths of a pixel.
+
+use std::time::Instant;
+use unremarkable_notes::lines::{BrushType, Color, Layer, Line, Page, Point};
+use unremarkable_notes::render::{Geometry, PdfRenderer, PngRenderer, Renderer, RenderOptions, SvgRenderer};
+
+const PAGES: usize = 20;
+const LINES: usize = 60;
+const POINTS: usize = 600;
+
+fn notebook() -> Vec<Page> {
+    (0..PAGES)
+        .map(|page| {
+            let lines = (0..LINES)
+                .map(|line| {
+                    let top = 150. + line as f32 * 28.;
+                    let points = (0..POINTS)
+                        .map(|i| {
+                            let x = 100. + i as f32 * 2.;
+                            // handwriting-like loops, different on each page
+                            let phase = (page * LINES + line) as f32;
+                            let y = top + 10. * (x / 9. + phase).sin() + 4. * (x / 3.).cos();
+                            let pressure = 0.6 + 0.3 * (x / 40.).sin();
+                            Point { x, y, speed: 1., direction: 0., width: 2. + 2. * pressure, pressure }
+                        })
+                        .collect();
+                    Line { brush_type: BrushType::Ballpoint, color: Color::Black, brush_base_size: 2., points }
+                })
+                .collect();
+            Page { layers: vec![Layer { name: None, lines }], text: None }
+        })
+        .collect()
+}
+
+fn main() {
+    let pages = notebook();
+    let pipelines = [
+        ("raw", Geometry::RAW),
+        ("dedup", Geometry { tolerance: 0., ..Geometry::default() }),
+        ("default", Geometry::default()),
+        ("tolerance 2", Geometry { tolerance: 2., ..Geometry::default() }),
+        ("smoothing 2", Geometry { smoothing: 2, ..Geometry::default() }),
+    ];
+    let renderers: [(&str, &dyn Renderer); 3] = [("pdf", &PdfRenderer), ("svg", &SvgRenderer), ("png", &PngRenderer)];
+
+    println!("{:<12} {:>10} {:>6} {:>12} {:>10}", "pipeline", "points", "format", "bytes", "ms");
+    for (name, geometry) in pipelines {
+        let started = Instant::now();
+        let prepared: Vec<Page> = pages.iter().map(|page| geometry.apply(page)).collect();
+        let pipeline = started.elapsed();
+        let points: usize = prepared.iter().flat_map(|p| &p.layers).flat_map(|l| &l.lines).map(|l| l.points.len()).sum();
+        let options = RenderOptions { scale: 0.5, ..RenderOptions::default() };
+
+        for (format, renderer) in renderers {
+            let started = Instant::now();
+            let mut size = 0;
+            if renderer.multi_page() {
+                let all: Vec<(usize, &Page)> = prepared.iter().enumerate().collect();
+                let mut output = Vec::new();
+                renderer.render(&mut output, &all, &options).expect("Could not render");
+                size += output.len();
+            } else {
+                for page in prepared.iter().enumerate() {
+                    let mut output = Vec::new();
+                    renderer.render(&mut output, &[page], &options).expect("Could not render");
+                    size += output.len();
+                }
+            }
+            let elapsed = (started.elapsed() + pipeline).as_secs_f64() * 1000.;
+            println!("{:<12} {:>10} {:>6} {:>12} {:>10.1}", name, points, format, size, elapsed);
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use std::path::PathBuf;
-use clap::{Parser, Subcommand};
-use unremarkable_notes::{config, sync, storage};
-use unremarkable_notes::storage::{Store, ItemType};
+use clap::{Parser, Subcommand, ValueEnum};
+use unremarkable_notes::{config, sync, storage, render::{Crop, DeviceProfile, Geometry, LayerSelection, Orientation, PageRanges, PdfRenderer, PngRenderer, RenderOptions, SvgRenderer}};
+use unremarkable_notes::storage::{Store, ItemType, Node, FileSystemStore, Filter};
 
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
@@ -35,12 +35,216 @@ enum SyncCommands {
 /// Interact with locally stored files
 enum StoreCommands {
     /// List all documents
-    List {},
+    List {
+        #[clap(long, value_enum, default_value_t = ItemFilter::Live)]
+        filter: ItemFilter,
+    },
+    /// Manage the trash
+    Trash {
+        #[clap(subcommand)]
+        command: TrashCommands,
+    },
+    /// Import pdf and epub files
+    Import {
+        #[clap(value_parser, required = true)]
+        files: Vec<PathBuf>,
+        /// Path of the collection to import into, such as /Work/Papers
+        #[clap(long, value_parser)]
+        into: Option<String>,
+    },
+    /// Create a blank notebook
+    New {
+        #[clap(value_parser)]
+        name: String,
+        /// Path of the collection to create the notebook in, such as /Team/Meetings
+        #[clap(long, value_parser)]
+        into: Option<String>,
+        /// Number of pages, at least one as the tablet can't open empty notebooks
+        #[clap(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..), default_value_t = 1)]
+        pages: usize,
+        /// Template of each page, the last one is used for all remaining pages
+        #[clap(long, value_parser, default_value = "Blank")]
+        template: Vec<String>,
+    },
+    /// Print the folder hierarchy
+    Tree {},
     /// Render a given document
     Render {
+        /// Uuid or path of visible names, such as /Work/Papers/foo
+        #[clap(value_parser)]
+        id: String,
+        #[clap(long, value_enum, default_value_t = RenderFormat::Svg)]
+        format: RenderFormat,
+        /// Resolution of png output, the tablet's screen has 226 dpi, or 229 on the Paper Pro
+        #[clap(long, value_parser, default_value_t = 226.)]
+        dpi: f32,
+        /// Width of png output in pixels, instead of --dpi
+        #[clap(long, value_parser)]
+        width: Option<u32>,
+        /// Pages to render, counted from 1, such as 1-5,8. All pages by default
+        #[clap(long, value_parser)]
+        pages: Option<PageRanges>,
+        /// Layers to render, by name or counted from 1, such as "Sketch,Final". All layers by default
+        #[clap(long, value_parser)]
+        layers: Option<LayerSelection>,
+        /// How far simplified lines may stray from the original points in pixels, instead of the one in the config
+        #[clap(long, value_parser)]
+        tolerance: Option<f32>,
+        /// Points to interpolate between each two points of a line, instead of the number in the config
+        #[clap(long, value_parser)]
+        smoothing: Option<u32>,
+        /// Directory to write one file per page to, or a single file of all pages for pdf output
+        #[clap(long, value_parser, default_value = ".")]
+        out: PathBuf,
+        /// Part of each page to render: all of it, the area containing ink, or the document's custom zoom
+        #[clap(long, value_enum, default_value_t = PageCrop::Page)]
+        crop: PageCrop,
+        /// Describe each line in a comment of svg output
+        #[clap(long, value_parser)]
+        debug_dump: bool,
+        /// Tablet the document was written on, instead of the one in the config
+        #[clap(long, value_enum)]
+        device: Option<Device>,
+        /// Turn pages, instead of keeping the orientation they were written in
+        #[clap(long, value_enum)]
+        orientation: Option<PageOrientation>,
+        /// Show pages as the tablet's screen does, with colors in grey unless it has a color display
+        #[clap(long, value_parser)]
+        preview: bool,
+    },
+    /// Print the typed text of a given document
+    Text {
+        /// Uuid or path of visible names, such as /Work/Papers/foo
+        #[clap(value_parser)]
+        id: String,
+        #[clap(long, value_enum, default_value_t = TextFormat::Plain)]
+        format: TextFormat,
+    },
+    /// Export the highlights of a given document
+    ExportHighlights {
+        /// Uuid or path of visible names, such as /Work/Papers/foo
+        #[clap(value_parser)]
+        id: String,
+        #[clap(long, value_enum, default_value_t = HighlightsFormat::Markdown)]
+        format: HighlightsFormat,
+        /// Directory to write the exported file to
+        #[clap(long, value_parser, default_value = ".")]
+        out: PathBuf,
+        /// Embed handwritten notes as SVG
+        #[clap(long, value_parser)]
+        notes: bool,
+    },
+    /// Export a given document as Xournal++ file, with the original PDF as background
+    ExportXopp {
+        /// Uuid or path of visible names, such as /Work/Papers/foo
+        #[clap(value_parser)]
+        id: String,
+        /// Directory to write the exported file to
+        #[clap(long, value_parser, default_value = ".")]
+        out: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum TrashCommands {
+    /// List trashed documents and collections
+    List {},
+    /// Move an item out of the trash
+    Restore {
+        /// Uuid or path of visible names, such as /Work/Papers/foo
         #[clap(value_parser)]
         id: String,
     },
+    /// Permanently remove everything in the trash
+    Empty {},
+}
+
+#[derive(Clone, ValueEnum)]
+enum ItemFilter {
+    Live,
+    Trashed,
+    Deleted,
+    All,
+}
+
+impl From<&ItemFilter> for Filter {
+    fn from(filter: &ItemFilter) -> Self {
+        match filter {
+            ItemFilter::Live => Filter::Live,
+            ItemFilter::Trashed => Filter::Trashed,
+            ItemFilter::Deleted => Filter::Deleted,
+            ItemFilter::All => Filter::All,
+        }
+    }
+}
+
+#[derive(Clone, ValueEnum)]
+enum PageCrop {
+    Page,
+    Content,
+    Zoom,
+}
+
+impl From<&PageCrop> for Crop {
+    fn from(crop: &PageCrop) -> Self {
+        match crop {
+            PageCrop::Page => Crop::Page,
+            PageCrop::Content => Crop::Content,
+            PageCrop::Zoom => Crop::Zoom,
+        }
+    }
+}
+
+#[derive(Clone, ValueEnum)]
+enum Device {
+    Remarkable1,
+    Remarkable2,
+    PaperPro,
+}
+
+impl From<&Device> for DeviceProfile {
+    fn from(device: &Device) -> Self {
+        match device {
+            Device::Remarkable1 => DeviceProfile::Remarkable1,
+            Device::Remarkable2 => DeviceProfile::Remarkable2,
+            Device::PaperPro => DeviceProfile::PaperPro,
+        }
+    }
+}
+
+#[derive(Clone, ValueEnum)]
+enum PageOrientation {
+    Portrait,
+    Landscape,
+}
+
+impl From<&PageOrientation> for Orientation {
+    fn from(orientation: &PageOrientation) -> Self {
+        match orientation {
+            PageOrientation::Portrait => Orientation::Portrait,
+            PageOrientation::Landscape => Orientation::Landscape,
+        }
+    }
+}
+
+#[derive(Clone, ValueEnum)]
+enum RenderFormat {
+    Svg,
+    Png,
+    Pdf,
+    /// The annotations of a PDF document on top of its original pages
+    AnnotatedPdf,
+}
+
+#[derive(Clone, ValueEnum)]
+enum HighlightsFormat {
+    Markdown,
+}
+
+#[derive(Clone, ValueEnum)]
+enum TextFormat {
+    Plain,
+    Markdown,
 }
 
 fn main() {
@@ -70,8 +274,8 @@ fn main() {
         Commands::Store { command } => {
             let store = storage::FileSystemStore::default();
             match command {
-                StoreCommands::List {  } => {
-                    let items = match store.all() {
+                StoreCommands::List { filter } => {
+                    let items = match store.all(filter.into()) {
                         Err(e) => panic!("Could not list files: {}", e),
                         Ok(v) => v
                     };
@@ -79,19 +283,198 @@ fn main() {
                         println!("{}", item)
                     }
                 }
-                StoreCommands::Render { id } => {
-                   let document = match store.load(id) {
+                StoreCommands::Trash { command } => match command {
+                    TrashCommands::List {  } => {
+                        let items = match store.all(Filter::Trashed) {
+                            Err(e) => panic!("Could not list trash: {}", e),
+                            Ok(v) => v
+                        };
+                        for item in items {
+                            println!("{}", item)
+                        }
+                    }
+                    TrashCommands::Restore { id } => {
+                        let id = match store.resolve_trashed(id) {
+                            Err(e) => panic!("Could not find {} in the trash: {}", id, e),
+                            Ok(item) => item.id.to_string(),
+                        };
+                        if let Err(e) = store.restore(&id) {
+                            panic!("Could not restore {}: {}", id, e);
+                        }
+                    }
+                    TrashCommands::Empty {  } => {
+                        match store.empty_trash() {
+                            Err(e) => panic!("Could not empty trash: {}", e),
+                            Ok(items) => for item in items {
+                                println!("Removed {}", item)
+                            }
+                        }
+                    }
+                },
+                StoreCommands::Import { files, into } => {
+                    let parent = into.as_deref().map(|into| resolve(&store, into));
+                    for file in files {
+                        match store.import(file, parent) {
+                            Err(e) => panic!("Could not import {}: {}", file.display(), e),
+                            Ok(item) => println!("Imported {}", item),
+                        }
+                    }
+                }
+                StoreCommands::New { name, into, pages, template } => {
+                    let parent = into.as_deref().map(|into| resolve(&store, into));
+                    let last = template.last().cloned().unwrap_or_else(|| "Blank".to_string());
+                    let templates: Vec<String> = (0..*pages)
+                        .map(|page| template.get(page).cloned().unwrap_or_else(|| last.clone()))
+                        .collect();
+                    match store.create_notebook(name, parent, &templates) {
+                        Err(e) => panic!("Could not create notebook: {}", e),
+                        Ok(item) => println!("Created {}", item),
+                    }
+                }
+                StoreCommands::Tree {  } => {
+                    let tree = match store.tree() {
+                        Err(e) => panic!("Could not list files: {}", e),
+                        Ok(v) => v
+                    };
+                    print_nodes(&store, &tree.children, 0);
+                    if !tree.orphans.is_empty() {
+                        println!("\nOrphaned:");
+                        print_nodes(&store, &tree.orphans, 1);
+                    }
+                }
+                StoreCommands::Render { id, format, dpi, width, pages, layers, tolerance, smoothing, out, crop, debug_dump, device, orientation, preview } => {
+                   let document = match store.load(&resolve(&store, id).to_string()) {
                         Err(e) => panic!("Could not load document: {}", e),
                         Ok(v) => match v {
                             ItemType::Document(d) => d,
                             ItemType::Collection(_) => panic!("Can't render a collection")
                         }
                     };
-                    if let Err(e) = document.to_svg(&store, &PathBuf::from("test.svg"), 1) {
-                        panic!("Could not load document: {}", e);
+                    let templates = match document.load_templates(&store, config.templates.as_deref()) {
+                        Err(e) => panic!("Could not load templates: {}", e),
+                        Ok(v) => v
+                    };
+                    let device = device.as_ref().map_or(config.device, DeviceProfile::from);
+                    let scale = match format {
+                        RenderFormat::Png => width.map_or(device.scale_at(*dpi), |width| width as f32 / device.size().0),
+                        _ => 1.,
+                    };
+                    let options = RenderOptions {
+                        pages: pages.clone(),
+                        layers: layers.clone(),
+                        geometry: Geometry {
+                            tolerance: tolerance.unwrap_or(config.geometry.tolerance),
+                            smoothing: smoothing.unwrap_or(config.geometry.smoothing),
+                            ..config.geometry
+                        },
+                        palette: match *preview && !device.is_color() {
+                            true => config.palette.greyscale(),
+                            false => config.palette.clone(),
+                        },
+                        templates,
+                        crop: crop.into(),
+                        orientation: orientation.as_ref().map(Orientation::from),
+                        device,
+                        scale,
+                        debug_dump: *debug_dump,
+                        ..RenderOptions::default()
+                    };
+                    let file = out.join(format!("{}.pdf", document.metadata.visible_name.replace('/', "-")));
+                    let result = match format {
+                        RenderFormat::Svg => document.render(&store, &SvgRenderer, out, &options),
+                        RenderFormat::Png => document.render(&store, &PngRenderer, out, &options),
+                        RenderFormat::Pdf => document.render(&store, &PdfRenderer, &file, &options),
+                        RenderFormat::AnnotatedPdf => document.to_annotated_pdf(&store, &file, &options).map(|()| vec![file.clone()]),
+                    };
+                    match result {
+                        Err(e) => panic!("Could not render document: {}", e),
+                        Ok(paths) => for path in paths {
+                            println!("{}", path.display())
+                        }
+                    }
+                }
+                StoreCommands::Text { id, format } => {
+                    let document = match store.load(&resolve(&store, id).to_string()) {
+                        Err(e) => panic!("Could not load document: {}", e),
+                        Ok(v) => match v {
+                            ItemType::Document(d) => d,
+                            ItemType::Collection(_) => panic!("Can't extract text from a collection")
+                        }
+                    };
+                    let pages = match document.text(&store) {
+                        Err(e) => panic!("Could not extract text: {}", e),
+                        Ok(v) => v
+                    };
+                    for text in pages.iter().filter(|t| !t.is_empty()) {
+                        match format {
+                            TextFormat::Plain => println!("{}\n", text.to_plain()),
+                            TextFormat::Markdown => println!("{}", text.to_markdown()),
+                        }
+                    }
+                }
+                StoreCommands::ExportHighlights { id, format, out, notes } => {
+                    let document = match store.load(&resolve(&store, id).to_string()) {
+                        Err(e) => panic!("Could not load document: {}", e),
+                        Ok(v) => match v {
+                            ItemType::Document(d) => d,
+                            ItemType::Collection(_) => panic!("Can't export highlights of a collection")
+                        }
+                    };
+                    let result = match format {
+                        HighlightsFormat::Markdown => {
+                            let name = document.metadata.visible_name.replace('/', "-");
+                            document.to_markdown(&store, &out.join(format!("{}.md", name)), *notes, &config.palette)
+                        }
+                    };
+                    if let Err(e) = result {
+                        panic!("Could not export highlights: {}", e);
+                    }
+                }
+                StoreCommands::ExportXopp { id, out } => {
+                    let document = match store.load(&resolve(&store, id).to_string()) {
+                        Err(e) => panic!("Could not load document: {}", e),
+                        Ok(v) => match v {
+                            ItemType::Document(d) => d,
+                            ItemType::Collection(_) => panic!("Can't export a collection")
+                        }
+                    };
+                    let options = RenderOptions {
+                        palette: config.palette.clone(),
+                        geometry: config.geometry,
+                        device: config.device,
+                        ..RenderOptions::default()
+                    };
+                    let path = out.join(format!("{}.xopp", document.metadata.visible_name.replace('/', "-")));
+                    match document.to_xopp(&store, &path, &options) {
+                        Err(e) => panic!("Could not export document: {}", e),
+                        Ok(()) => println!("{}", path.display()),
                     }
                 }
             }
         }
     }
 }
+
+/// Looks up the uuid of an item given either as uuid or as a path of visible names.
+fn resolve(store: &FileSystemStore, reference: &str) -> uuid::Uuid {
+    match store.resolve(reference) {
+        Err(e) => panic!("Could not find {}: {}", reference, e),
+        Ok(item) => item.id,
+    }
+}
+
+fn print_nodes(store: &FileSystemStore, nodes: &[Node], depth: usize) {
+    for node in nodes {
+        let icon = match store.load(&node.item.id.to_string()) {
+            Ok(ItemType::Collection(_)) => "📁",
+            Ok(ItemType::Document(d)) => match d.content.file_type.as_str() {
+                "pdf" => "📄",
+                "epub" => "📖",
+                _ => "📓",
+            },
+            Err(_) => "❓",
+        };
+        println!("{}{} {}", "    ".repeat(depth), icon, node.item.visible_name);
+        print_nodes(store, &node.children, depth + 1);
+    }
+}
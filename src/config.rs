@@ -36,6 +36,16 @@ pub struct Config {
     pub description: String,
     pub id: uuid::Uuid,
     pub token: Option<Token>,
+    /// A local copy of the device's `/usr/share/remarkable/templates`, to draw page backgrounds from.
+    #[serde(default)]
+    pub templates: Option<PathBuf>,
+    #[serde(default)]
+    pub palette: crate::render::Palette,
+    #[serde(default)]
+    pub geometry: crate::render::Geometry,
+    /// The tablet documents were written on, `remarkable1`, `remarkable2` or `paper-pro`.
+    #[serde(default)]
+    pub device: crate::render::DeviceProfile,
     #[serde(skip)]
     pub path: PathBuf,
 }
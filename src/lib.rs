@@ -2,19 +2,49 @@
 #![doc(issue_tracker_base_url = "https://github.com/phaer/unremarkable/issues/")]
 
 pub mod config;
+pub mod lines;
 pub mod storage;
 pub mod sync;
 pub mod render;
+mod utils;
 
 #[cfg(test)]
 mod tests {
     use uuid::Uuid;
-    use crate::storage::{Store, FileSystemStore, ItemType, error::Result};
+    use crate::storage::{Store, FileSystemStore, ItemType, Filter, TRASH, error::{Error, Result}};
+    use crate::render::{PdfRenderer, PngRenderer, Renderer, RenderOptions};
+
+    const FOLDER: &str = "8b6a4c1e-4f5a-4c6e-9d3b-2f1f0c6f9a11";
+    const NOTEBOOK: &str = "0f4a8f6e-2c3b-4d5e-8f9a-1b2c3d4e5f60";
+
+    /// A store in a temporary directory, holding a folder and a notebook.
+    fn temporary_store() -> (tempfile::TempDir, FileSystemStore) {
+        let directory = tempfile::tempdir().expect("Could not create temporary directory");
+        let metadata = |type_: &str, name: &str| format!(r#"{{
+    "deleted": false,
+    "lastModified": "1660000000000",
+    "metadatamodified": false,
+    "modified": false,
+    "parent": "",
+    "pinned": false,
+    "synced": true,
+    "type": "{}",
+    "version": 1,
+    "visibleName": "{}"
+}}
+"#, type_, name);
+        std::fs::write(directory.path().join(FOLDER).with_extension("metadata"), metadata("CollectionType", "Work"))
+            .expect("Could not write folder");
+        std::fs::write(directory.path().join(NOTEBOOK).with_extension("metadata"), metadata("DocumentType", "Notes"))
+            .expect("Could not write notebook");
+        let store = FileSystemStore::try_from(directory.path()).expect("Could not open store");
+        (directory, store)
+    }
 
     #[test]
     fn it_can_list_and_parse_all_notebooks() -> Result<()> {
         let store = FileSystemStore::default();
-        let items = store.all()?;
+        let items = store.all(Filter::All)?;
         assert!(items.len() > 0);
         for item in items {
            match store.load(&item.id.to_string())? {
@@ -33,6 +63,345 @@ mod tests {
         assert!(file.is_err())
     }
 
+    #[test]
+    fn it_can_modify_items() -> Result<()> {
+        let (_directory, store) = temporary_store();
+        store.rename(NOTEBOOK, "Meeting notes")?;
+        store.move_to(NOTEBOOK, Some(Uuid::parse_str(FOLDER).expect("Invalid test id")))?;
+        store.set_pinned(NOTEBOOK, true)?;
+
+        let item = store.by_id(NOTEBOOK)?;
+        assert_eq!(item.visible_name, "Meeting notes");
+        assert_eq!(item.parent.map(|p| p.to_string()).as_deref(), Some(FOLDER));
+        assert!(item.pinned);
+        assert!(item.modified);
+        assert_eq!(item.version, 4);
+
+        store.trash(NOTEBOOK)?;
+        assert_eq!(store.by_id(NOTEBOOK)?.parent, Some(TRASH));
+        store.restore(NOTEBOOK)?;
+        let item = store.by_id(NOTEBOOK)?;
+        assert_eq!(item.parent, None);
+        assert!(matches!(store.restore(NOTEBOOK), Err(Error::NotTrashed { .. })));
+        assert_eq!(store.by_id(NOTEBOOK)?.version, item.version);
+        assert_eq!(store.by_id(NOTEBOOK)?.last_modified, item.last_modified);
+
+        assert!(store.move_to(FOLDER, Some(Uuid::parse_str(NOTEBOOK).expect("Invalid test id"))).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_refuses_to_move_collections_into_themselves() -> Result<()> {
+        let (directory, store) = temporary_store();
+        let nested = "5d2e7c1a-9b3f-4e8d-a6c4-7f1b2e3d4c5a";
+        std::fs::copy(directory.path().join(FOLDER).with_extension("metadata"), directory.path().join(nested).with_extension("metadata"))
+            .expect("Could not write nested folder");
+        let folder = Uuid::parse_str(FOLDER).expect("Invalid test id");
+        store.move_to(nested, Some(folder))?;
+
+        assert!(matches!(store.move_to(FOLDER, Some(folder)), Err(Error::MoveIntoItself { .. })));
+        let within = Uuid::parse_str(nested).expect("Invalid test id");
+        assert!(matches!(store.move_to(FOLDER, Some(within)), Err(Error::MoveIntoItself { .. })));
+        assert_eq!(store.by_id(FOLDER)?.parent, None);
+        store.move_to(nested, None)?;
+        store.move_to(FOLDER, Some(within))?;
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_resolve_items_by_path() -> Result<()> {
+        let (_directory, store) = temporary_store();
+        assert_eq!(store.resolve("/Notes")?.id.to_string(), NOTEBOOK);
+        assert_eq!(store.resolve(NOTEBOOK)?.visible_name, "Notes");
+        store.move_to(NOTEBOOK, Some(Uuid::parse_str(FOLDER).expect("Invalid test id")))?;
+        assert_eq!(store.resolve("/Work/Notes")?.id.to_string(), NOTEBOOK);
+        assert_eq!(store.tree()?.path_of(&store.resolve("Work/Notes")?.id).as_deref(), Some("/Work/Notes"));
+        assert!(store.resolve("/Notes").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_empty_the_trash() -> Result<()> {
+        let (directory, store) = temporary_store();
+        let pages = directory.path().join(NOTEBOOK);
+        std::fs::create_dir(&pages).expect("Could not create page directory");
+        std::fs::write(pages.join("page.rm"), b"").expect("Could not write page");
+        let copy = directory.path().join(format!("{}_1.zip", NOTEBOOK));
+        std::fs::write(&copy, b"").expect("Could not write copy");
+        store.move_to(NOTEBOOK, Some(Uuid::parse_str(FOLDER).expect("Invalid test id")))?;
+        store.trash(FOLDER)?;
+
+        let ids = |filter| -> Result<Vec<String>> {
+            Ok(store.all(filter)?.iter().map(|item| item.id.to_string()).collect())
+        };
+        assert!(ids(Filter::Live)?.is_empty());
+        assert_eq!(ids(Filter::Trashed)?.len(), 2);
+
+        store.empty_trash()?;
+        assert!(ids(Filter::All)?.is_empty());
+        assert!(!pages.exists());
+        assert!(!copy.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_import_pdfs() -> Result<()> {
+        let (directory, store) = temporary_store();
+        let source = tempfile::tempdir().expect("Could not create temporary directory");
+        let pdf = source.path().join("Proceedings.pdf");
+        let mut output = std::fs::File::create(&pdf).expect("Could not create pdf");
+        let pages = vec![crate::lines::Page::default(); 3];
+        let pages: Vec<_> = pages.iter().enumerate().collect();
+        PdfRenderer.render(&mut output, &pages, &Default::default()).expect("Could not write pdf");
+
+        let item = store.import(&pdf, Some(Uuid::parse_str(FOLDER).expect("Invalid test id")))?;
+        assert_eq!(store.resolve("/Work/Proceedings")?.id, item.id);
+        assert!(directory.path().join(item.id.to_string()).with_extension("pdf").is_file());
+        assert!(directory.path().join(item.id.to_string()).is_dir());
+        match store.load(&item.id.to_string())? {
+            ItemType::Document(d) => {
+                assert_eq!(d.content.file_type, "pdf");
+                assert_eq!(d.content.page_count, 3);
+                assert_eq!(d.content.pages.len(), 3);
+            }
+            ItemType::Collection(_) => panic!("Imported pdf as collection"),
+        }
+
+        assert!(store.import(&source.path().join("notes.txt"), None).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_attaches_xopp_backgrounds_only_after_rendering() -> Result<()> {
+        let (_directory, store) = temporary_store();
+        let source = tempfile::tempdir().expect("Could not create temporary directory");
+        let pdf = source.path().join("Slides.pdf");
+        let mut output = std::fs::File::create(&pdf).expect("Could not create pdf");
+        PdfRenderer.render(&mut output, &[(0, &crate::lines::Page::default())], &Default::default()).expect("Could not write pdf");
+        let item = store.import(&pdf, None)?;
+
+        match store.load(&item.id.to_string())? {
+            ItemType::Document(d) => {
+                // a directory can't be written to, so rendering fails
+                let failing = source.path().join("Slides.xopp");
+                std::fs::create_dir(&failing).expect("Could not create directory");
+                assert!(d.to_xopp(&store, &failing, &Default::default()).is_err());
+                assert!(!source.path().join("Slides.xopp.bg.pdf").exists());
+
+                let path = source.path().join("Exported.xopp");
+                d.to_xopp(&store, &path, &Default::default())?;
+                assert!(path.is_file());
+                assert!(source.path().join("Exported.xopp.bg.pdf").is_file());
+            }
+            ItemType::Collection(_) => panic!("Imported pdf as collection"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_can_create_notebooks() -> Result<()> {
+        let (directory, store) = temporary_store();
+        let templates = vec!["Blank".to_string(), "P Lines small".to_string()];
+        let item = store.create_notebook("Sprint 12", Some(Uuid::parse_str(FOLDER).expect("Invalid test id")), &templates)?;
+        assert_eq!(store.resolve("/Work/Sprint 12")?.id, item.id);
+
+        let pagedata = std::fs::read_to_string(directory.path().join(item.id.to_string()).with_extension("pagedata"))
+            .expect("Could not read pagedata");
+        assert_eq!(pagedata, "Blank\nP Lines small\n");
+        match store.load(&item.id.to_string())? {
+            ItemType::Document(d) => {
+                assert_eq!(d.templates(&store)?, templates);
+                assert_eq!(d.content.file_type, "notebook");
+                for page in &d.content.pages {
+                    let path = directory.path().join(item.id.to_string()).join(page.to_string()).with_extension("rm");
+                    let data = std::fs::read(path).expect("Could not read page");
+                    assert!(data.starts_with(b"reMarkable .lines file, version=5"));
+                }
+                assert_eq!(d.content.pages.len(), 2);
+            }
+            ItemType::Collection(_) => panic!("Created notebook as collection"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_refuses_to_create_notebooks_without_pages() -> Result<()> {
+        let (_directory, store) = temporary_store();
+        assert!(matches!(store.create_notebook("Empty", None, &[]), Err(Error::EmptyNotebook { .. })));
+        assert!(store.resolve("/Empty").is_err());
+        Ok(())
+    }
+
+    /// Loads and saves every item of the sample files in `tests/fixtures/<firmware>`,
+    /// which have to be written back exactly as `xochitl` wrote them.
+    #[test]
+    fn it_round_trips_sample_items_of_each_firmware() -> Result<()> {
+        let fixtures = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        for firmware in std::fs::read_dir(fixtures).expect("Could not list fixtures") {
+            let samples = firmware.expect("Could not list fixtures").path();
+            let directory = tempfile::tempdir().expect("Could not create temporary directory");
+            let files: Vec<_> = std::fs::read_dir(&samples).expect("Could not list samples")
+                .map(|file| file.expect("Could not list samples").path())
+                .collect();
+            for file in &files {
+                std::fs::copy(file, directory.path().join(file.file_name().expect("Sample without name")))
+                    .expect("Could not copy sample");
+            }
+
+            let store = FileSystemStore::try_from(directory.path())?;
+            let items = store.all(Filter::All)?;
+            assert!(!items.is_empty(), "No samples for firmware {}", samples.display());
+            for item in items {
+                store.save(&store.load(&item.id.to_string())?)?;
+            }
+            for file in &files {
+                let written = directory.path().join(file.file_name().expect("Sample without name"));
+                assert_eq!(
+                    std::fs::read_to_string(written).expect("Could not read written sample"),
+                    std::fs::read_to_string(file).expect("Could not read sample"),
+                    "{} changed", file.display()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_reads_pages_from_c_pages() -> Result<()> {
+        let (directory, store) = temporary_store();
+        std::fs::write(directory.path().join(NOTEBOOK).with_extension("content"), r#"{
+    "cPages": {
+        "pages": [
+            { "id": "22222222-2222-4222-8222-222222222222", "idx": { "timestamp": "1:3", "value": "bb" }, "template": { "timestamp": "1:3", "value": "P Grid small" } },
+            { "id": "33333333-3333-4333-8333-333333333333", "idx": { "timestamp": "1:4", "value": "bc" }, "deleted": { "timestamp": "1:5", "value": 1 } },
+            { "id": "11111111-1111-4111-8111-111111111111", "idx": { "timestamp": "1:2", "value": "ba" }, "redir": { "timestamp": "1:2", "value": 4 }, "verticalScroll": { "timestamp": "1:6", "value": 2808 } }
+        ]
+    },
+    "customZoomCenterX": -100,
+    "customZoomCenterY": 936.5,
+    "customZoomScale": 2,
+    "fileType": "pdf",
+    "zoomMode": "customZoom"
+}
+"#).expect("Could not write content");
+        match store.load(NOTEBOOK)? {
+            ItemType::Document(d) => {
+                let pages: Vec<String> = d.content.pages.iter().map(|p| p.to_string()).collect();
+                assert_eq!(pages, ["11111111-1111-4111-8111-111111111111", "22222222-2222-4222-8222-222222222222"]);
+                assert_eq!(d.content.redirection_page_map, [4, -1]);
+                assert_eq!(d.content.page_heights(), [Some(2808.), None]);
+                assert_eq!(d.templates(&store)?, ["Blank", "P Grid small"]);
+                assert_eq!(d.content.zoom(), Some(crate::render::Zoom { center: (-100., 936.5), scale: 2. }));
+            }
+            ItemType::Collection(_) => panic!("Loaded notebook as collection"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_renders_selected_pages_into_the_output_directory() -> Result<()> {
+        let (directory, store) = temporary_store();
+        std::fs::write(directory.path().join(NOTEBOOK).with_extension("content"), format!(
+            r#"{{ "fileType": "notebook", "pageCount": 3, "pages": ["{}", "{}", "{}"] }}"#,
+            Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4()
+        )).expect("Could not write content");
+        let out = tempfile::tempdir().expect("Could not create temporary directory");
+        let options = RenderOptions { pages: Some("2-3".parse().expect("valid ranges")), ..RenderOptions::default() };
+
+        match store.load(NOTEBOOK)? {
+            ItemType::Document(d) => {
+                let paths = d.render(&store, &PngRenderer, out.path(), &options)?;
+                assert_eq!(paths, [out.path().join("Notes-002.png"), out.path().join("Notes-003.png")]);
+                assert!(paths.iter().all(|path| path.is_file()));
+            }
+            ItemType::Collection(_) => panic!("Loaded notebook as collection"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_renders_single_pages_to_png_at_a_resolution() -> Result<()> {
+        let (directory, store) = temporary_store();
+        std::fs::write(directory.path().join(NOTEBOOK).with_extension("content"), format!(
+            r#"{{ "fileType": "notebook", "pageCount": 2, "pages": ["{}", "{}"] }}"#,
+            Uuid::new_v4(), Uuid::new_v4()
+        )).expect("Could not write content");
+        let out = tempfile::tempdir().expect("Could not create temporary directory");
+        let path = out.path().join("thumbnail.png");
+
+        match store.load(NOTEBOOK)? {
+            ItemType::Document(d) => {
+                // half the resolution of the screen
+                d.to_png(&store, &path, 1, 113.)?;
+                let decoder = png::Decoder::new(std::fs::File::open(&path).expect("Could not open png"));
+                let info = decoder.read_info().expect("Could not decode png");
+                assert_eq!((info.info().width, info.info().height), (702, 936));
+                assert!(matches!(d.to_png(&store, &path, 2, 113.), Err(Error::InvalidPage { page: 2, .. })));
+            }
+            ItemType::Collection(_) => panic!("Loaded notebook as collection"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_reads_highlights_per_page() -> Result<()> {
+        let (directory, store) = temporary_store();
+        let (first, second) = ("11111111-1111-4111-8111-111111111111", "22222222-2222-4222-8222-222222222222");
+        std::fs::write(directory.path().join(NOTEBOOK).with_extension("content"), format!(
+            r#"{{ "fileType": "pdf", "pageCount": 2, "pages": ["{}", "{}"] }}"#, first, second
+        )).expect("Could not write content");
+        let highlights = directory.path().join(NOTEBOOK).with_extension("highlights");
+        std::fs::create_dir(&highlights).expect("Could not create highlights directory");
+        let highlight = |text: &str, start: usize| format!(
+            r#"{{ "text": "{}", "start": {}, "length": {}, "rects": [] }}"#, text, start, text.len()
+        );
+        // stored out of order, and split into several groups as the tablet does
+        std::fs::write(highlights.join(second).with_extension("json"), format!(
+            r#"{{ "highlights": [[{}], [{}]] }}"#, highlight("third", 40), highlight("second", 10)
+        )).expect("Could not write highlights");
+        std::fs::write(highlights.join(first).with_extension("json"), format!(
+            r#"{{ "highlights": [[{}]] }}"#, highlight("first", 700)
+        )).expect("Could not write highlights");
+
+        match store.load(NOTEBOOK)? {
+            ItemType::Document(d) => {
+                let highlights = d.highlights(&store)?;
+                let pages: Vec<(usize, &str)> = highlights.iter().map(|h| (h.page, h.text.as_str())).collect();
+                assert_eq!(pages, [(0, "first"), (1, "second"), (1, "third")]);
+            }
+            ItemType::Collection(_) => panic!("Loaded notebook as collection"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_references_pages_of_the_original_in_markdown() -> Result<()> {
+        let (directory, store) = temporary_store();
+        let pages = ["11111111-1111-4111-8111-111111111111", "22222222-2222-4222-8222-222222222222", "33333333-3333-4333-8333-333333333333"];
+        std::fs::write(directory.path().join(NOTEBOOK).with_extension("content"), format!(
+            r#"{{ "fileType": "pdf", "pageCount": 3, "pages": ["{}", "{}", "{}"], "redirectionPageMap": [0, -1, 4] }}"#,
+            pages[0], pages[1], pages[2]
+        )).expect("Could not write content");
+        let highlights = directory.path().join(NOTEBOOK).with_extension("highlights");
+        std::fs::create_dir(&highlights).expect("Could not create highlights directory");
+        for (page, text) in pages[1..].iter().zip(["inserted", "original"]) {
+            std::fs::write(highlights.join(page).with_extension("json"), format!(
+                r#"{{ "highlights": [[{{ "text": "{}", "start": 0, "length": 1, "rects": [] }}]] }}"#, text
+            )).expect("Could not write highlights");
+        }
+
+        let out = tempfile::tempdir().expect("Could not create temporary directory");
+        let path = out.path().join("Notes.md");
+        match store.load(NOTEBOOK)? {
+            ItemType::Document(d) => d.to_markdown(&store, &path, false, &Default::default())?,
+            ItemType::Collection(_) => panic!("Loaded notebook as collection"),
+        }
+        let markdown = std::fs::read_to_string(&path).expect("Could not read markdown");
+        assert!(markdown.contains("## Page inserted after p. 1\n\n> inserted\n> — page inserted after p. 1\n"), "{}", markdown);
+        assert!(markdown.contains("## Page 5\n\n> original\n> — p. 5\n"), "{}", markdown);
+        Ok(())
+    }
+
     #[test]
     fn it_can_parse_epubs() -> Result<()> {
         let id = "7063a1a0-26e6-4941-aa0e-b8786aaf28bd";
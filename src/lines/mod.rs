@@ -0,0 +1,249 @@
+//! # Lines
+//!
+//! The page model of Remarkable´s `.rm` [lines files](crate::storage#remarkable-lines), shared by all renderers.
+//!
+//! Files up to version 5 are parsed by [lines-are-rusty](https://github.com/ax3l/lines-are-rusty) and converted,
+//! version 6 files, as written by firmware 3.x, are parsed natively by [`v6`](v6).
+
+pub mod text;
+pub mod v6;
+
+use serde::{Deserialize, Serialize};
+use snafu::{Snafu, ResultExt};
+use std::io::{Cursor, Read};
+
+#[derive(Snafu, Debug)]
+#[snafu(visibility(pub(crate)))]
+pub enum Error {
+    #[snafu(display("Unable to read lines: {}", source))]
+    Read { source: std::io::Error },
+    #[snafu(display("Unable to parse legacy lines: {}", source))]
+    Legacy { source: lines_are_rusty::Error },
+    #[snafu(display("Unexpected end of data at offset {}", offset))]
+    UnexpectedEof { offset: usize },
+    #[snafu(display("Expected tag {} of type {:#x} at offset {}", index, tag_type, offset))]
+    UnexpectedTag { index: u32, tag_type: u8, offset: usize },
+    #[snafu(display("Invalid string at offset {}", offset))]
+    InvalidString { offset: usize },
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// Length of the ascii header at the start of every lines file, padded with spaces.
+pub const HEADER_LENGTH: usize = 43;
+
+#[derive(Debug, Default, Clone)]
+pub struct Page {
+    pub layers: Vec<Layer>,
+    /// Typed text, only supported by version 6 files.
+    pub text: Option<text::Text>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Layer {
+    pub name: Option<String>,
+    pub lines: Vec<Line>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Line {
+    pub brush_type: BrushType,
+    pub color: Color,
+    pub brush_base_size: f32,
+    pub points: Vec<Point>,
+}
+
+/// A sampled point, in screen pixels with the origin in the top left corner.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Point {
+    pub x: f32,
+    pub y: f32,
+    pub speed: f32,
+    /// Direction of the pen in radians.
+    pub direction: f32,
+    pub width: f32,
+    /// Pressure between `0.0` and `1.0`.
+    pub pressure: f32,
+}
+
+/// Brush types, as numbered in lines files.
+///
+/// Firmware 2.x introduced a second set of numbers for most tools,
+/// both map to the same variant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrushType {
+    Paintbrush,
+    Pencil,
+    Ballpoint,
+    Marker,
+    Fineliner,
+    Highlighter,
+    Eraser,
+    MechanicalPencil,
+    EraseArea,
+    EraseAll,
+    SelectionBrush,
+    Calligraphy,
+    Shader,
+    Unknown(i32),
+}
+
+impl From<i32> for BrushType {
+    fn from(id: i32) -> Self {
+        match id {
+            0 | 12 => Self::Paintbrush,
+            1 | 14 => Self::Pencil,
+            2 | 15 => Self::Ballpoint,
+            3 | 16 => Self::Marker,
+            4 | 17 => Self::Fineliner,
+            5 | 18 => Self::Highlighter,
+            6 => Self::Eraser,
+            7 | 13 => Self::MechanicalPencil,
+            8 => Self::EraseArea,
+            9 => Self::EraseAll,
+            10 | 11 => Self::SelectionBrush,
+            21 => Self::Calligraphy,
+            23 => Self::Shader,
+            id => Self::Unknown(id),
+        }
+    }
+}
+
+/// Colors, as numbered in lines files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "i32", into = "i32")]
+pub enum Color {
+    Black,
+    Grey,
+    White,
+    Yellow,
+    Green,
+    Pink,
+    Blue,
+    Red,
+    GreyOverlap,
+    Highlight,
+    GreenHighlight,
+    Cyan,
+    Magenta,
+    YellowHighlight,
+    Unknown(i32),
+}
+
+impl From<i32> for Color {
+    fn from(id: i32) -> Self {
+        match id {
+            0 => Self::Black,
+            1 => Self::Grey,
+            2 => Self::White,
+            3 => Self::Yellow,
+            4 => Self::Green,
+            5 => Self::Pink,
+            6 => Self::Blue,
+            7 => Self::Red,
+            8 => Self::GreyOverlap,
+            9 => Self::Highlight,
+            10 => Self::GreenHighlight,
+            11 => Self::Cyan,
+            12 => Self::Magenta,
+            13 => Self::YellowHighlight,
+            id => Self::Unknown(id),
+        }
+    }
+}
+
+impl From<Color> for i32 {
+    fn from(color: Color) -> Self {
+        match color {
+            Color::Black => 0,
+            Color::Grey => 1,
+            Color::White => 2,
+            Color::Yellow => 3,
+            Color::Green => 4,
+            Color::Pink => 5,
+            Color::Blue => 6,
+            Color::Red => 7,
+            Color::GreyOverlap => 8,
+            Color::Highlight => 9,
+            Color::GreenHighlight => 10,
+            Color::Cyan => 11,
+            Color::Magenta => 12,
+            Color::YellowHighlight => 13,
+            Color::Unknown(id) => id,
+        }
+    }
+}
+
+/// A version 5 lines file of a single page with one empty layer, as firmware 3.x still reads those.
+pub fn empty_page() -> Vec<u8> {
+    let mut data = format!("{:<width$}", "reMarkable .lines file, version=5", width = HEADER_LENGTH).into_bytes();
+    data.extend_from_slice(&1u32.to_le_bytes());
+    data.extend_from_slice(&0u32.to_le_bytes());
+    data
+}
+
+/// Parses a lines file of any supported version into its pages.
+pub fn parse(reader: &mut dyn Read) -> Result<Vec<Page>> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data).context(ReadSnafu)?;
+    if data.starts_with(v6::HEADER) {
+        let body = data.get(HEADER_LENGTH..).ok_or(Error::UnexpectedEof { offset: data.len() })?;
+        return Ok(vec![v6::parse(body)?]);
+    }
+    let parsed = lines_are_rusty::LinesData::parse(&mut Cursor::new(data)).context(LegacySnafu)?;
+    Ok(parsed.pages.into_iter().map(Page::from).collect())
+}
+
+impl From<lines_are_rusty::Page> for Page {
+    fn from(page: lines_are_rusty::Page) -> Self {
+        let layers = page.layers
+            .into_iter()
+            .map(|layer| Layer {
+                name: None,
+                lines: layer.lines.into_iter().map(Line::from).collect(),
+            })
+            .collect();
+        Self { layers, text: None }
+    }
+}
+
+/// Maps every brush and color explicitly, so that ones added to lines-are-rusty fail to compile
+/// instead of being drawn as something else.
+impl From<lines_are_rusty::Line> for Line {
+    fn from(line: lines_are_rusty::Line) -> Self {
+        use lines_are_rusty::{BrushType as B, Color as C};
+        let brush_type = match line.brush_type {
+            B::Brush => BrushType::Paintbrush,
+            B::TiltPencil => BrushType::Pencil,
+            B::BallPoint | B::Pen => BrushType::Ballpoint,
+            B::Marker => BrushType::Marker,
+            B::Fineliner => BrushType::Fineliner,
+            B::Highlighter => BrushType::Highlighter,
+            B::Eraser => BrushType::Eraser,
+            B::SharpPencil => BrushType::MechanicalPencil,
+            B::EraseArea => BrushType::EraseArea,
+            B::EraseAll => BrushType::EraseAll,
+            B::SelectionBrush => BrushType::SelectionBrush,
+            B::Calligraphy => BrushType::Calligraphy,
+        };
+        let color = match line.color {
+            C::Black => Color::Black,
+            C::Grey => Color::Grey,
+            C::White => Color::White,
+            C::Blue => Color::Blue,
+            C::Red => Color::Red,
+        };
+        let points = line.points
+            .into_iter()
+            .map(|p| Point {
+                x: p.x,
+                y: p.y,
+                speed: p.speed,
+                direction: p.direction,
+                width: p.width,
+                pressure: p.pressure,
+            })
+            .collect();
+        Self { brush_type, color, brush_base_size: line.brush_base_size, points }
+    }
+}
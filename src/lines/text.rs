@@ -0,0 +1,224 @@
+//! Typed text of a page, as introduced with firmware 3.x.
+//!
+//! Text is stored as a CRDT sequence of strings, paragraph styles refer to the
+//! newline character starting a paragraph, inline formatting is stored as
+//! markers in the sequence itself.
+
+use super::v6::{toposort, CrdtId, SequenceItem, END_MARKER};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+const FORMAT_BOLD_START: u32 = 1;
+const FORMAT_BOLD_END: u32 = 2;
+const FORMAT_ITALIC_START: u32 = 3;
+const FORMAT_ITALIC_END: u32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParagraphStyle {
+    Basic,
+    Plain,
+    Heading,
+    Bold,
+    Bullet,
+    Bullet2,
+    Checkbox,
+    CheckboxChecked,
+    Unknown(u8),
+}
+
+impl From<u8> for ParagraphStyle {
+    fn from(code: u8) -> Self {
+        match code {
+            0 => Self::Basic,
+            1 => Self::Plain,
+            2 => Self::Heading,
+            3 => Self::Bold,
+            4 => Self::Bullet,
+            5 => Self::Bullet2,
+            6 => Self::Checkbox,
+            7 => Self::CheckboxChecked,
+            code => Self::Unknown(code),
+        }
+    }
+}
+
+/// A run of characters sharing the same inline formatting.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Span {
+    pub text: String,
+    pub bold: bool,
+    pub italic: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Paragraph {
+    pub style: ParagraphStyle,
+    pub spans: Vec<Span>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Text {
+    pub paragraphs: Vec<Paragraph>,
+}
+
+/// The content of a text item: a string, or an inline formatting marker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextValue {
+    Text(String),
+    Format(u32),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Char {
+    Char(char),
+    Format(u32),
+}
+
+impl Paragraph {
+    pub fn text(&self) -> String {
+        self.spans.iter().map(|span| span.text.as_str()).collect()
+    }
+
+    fn push(&mut self, c: char, bold: bool, italic: bool) {
+        match self.spans.last_mut() {
+            Some(span) if span.bold == bold && span.italic == italic => span.text.push(c),
+            _ => self.spans.push(Span { text: c.to_string(), bold, italic }),
+        }
+    }
+}
+
+impl Text {
+    /// Reconstructs text from the items of its CRDT sequence and the styles of its paragraphs.
+    pub fn from_items(items: Vec<SequenceItem<TextValue>>, styles: &HashMap<CrdtId, ParagraphStyle>) -> Self {
+        let chars = toposort(items.into_iter().flat_map(expand).collect());
+
+        let style = |id| styles.get(&id).copied().unwrap_or(ParagraphStyle::Plain);
+        let mut paragraphs = vec![Paragraph { style: style(END_MARKER), spans: Vec::new() }];
+        let (mut bold, mut italic) = (false, false);
+        for item in chars {
+            let paragraph = paragraphs.last_mut().expect("at least one paragraph");
+            match item.value {
+                None => {}
+                Some(Char::Char('\n')) => paragraphs.push(Paragraph { style: style(item.id), spans: Vec::new() }),
+                Some(Char::Char(c)) => paragraph.push(c, bold, italic),
+                Some(Char::Format(FORMAT_BOLD_START)) => bold = true,
+                Some(Char::Format(FORMAT_BOLD_END)) => bold = false,
+                Some(Char::Format(FORMAT_ITALIC_START)) => italic = true,
+                Some(Char::Format(FORMAT_ITALIC_END)) => italic = false,
+                Some(Char::Format(_)) => {}
+            }
+        }
+        Self { paragraphs }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paragraphs.iter().all(|p| p.spans.is_empty())
+    }
+
+    pub fn to_plain(&self) -> String {
+        self.paragraphs
+            .iter()
+            .map(Paragraph::text)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut output = String::new();
+        for paragraph in &self.paragraphs {
+            let prefix = match paragraph.style {
+                ParagraphStyle::Heading => "# ",
+                ParagraphStyle::Bullet => "- ",
+                ParagraphStyle::Bullet2 => "  - ",
+                ParagraphStyle::Checkbox => "- [ ] ",
+                ParagraphStyle::CheckboxChecked => "- [x] ",
+                _ => "",
+            };
+            output.push_str(prefix);
+            for span in &paragraph.spans {
+                let bold = span.bold || paragraph.style == ParagraphStyle::Bold;
+                let marker = match (bold, span.italic) {
+                    (true, true) => "***",
+                    (true, false) => "**",
+                    (false, true) => "*",
+                    (false, false) => "",
+                };
+                let _ = write!(output, "{}{}{}", marker, span.text, marker);
+            }
+            output.push('\n');
+        }
+        output
+    }
+}
+
+/// Splits an item into one item per character, as styles and neighbours may refer to any of them.
+fn expand(item: SequenceItem<TextValue>) -> Vec<SequenceItem<Char>> {
+    let (values, deleted_length): (Vec<Option<Char>>, u32) = match item.value {
+        _ if item.deleted_length > 0 => (vec![None; item.deleted_length as usize], 1),
+        Some(TextValue::Text(text)) => (text.chars().map(|c| Some(Char::Char(c))).collect(), 0),
+        Some(TextValue::Format(code)) => (vec![Some(Char::Format(code))], 0),
+        None => (Vec::new(), 0),
+    };
+
+    let count = values.len() as u64;
+    values
+        .into_iter()
+        .enumerate()
+        .map(|(i, value)| {
+            let i = i as u64;
+            let id = CrdtId(item.id.0, item.id.1 + i);
+            let left = if i == 0 { item.left } else { CrdtId(item.id.0, item.id.1 + i - 1) };
+            let right = if i + 1 == count { item.right } else { CrdtId(item.id.0, item.id.1 + i + 1) };
+            SequenceItem { id, left, right, deleted_length, value }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(id: CrdtId, left: CrdtId, right: CrdtId, value: TextValue) -> SequenceItem<TextValue> {
+        SequenceItem { id, left, right, deleted_length: 0, value: Some(value) }
+    }
+
+    #[test]
+    fn it_reconstructs_paragraphs_with_styles() {
+        let items = vec![
+            item(CrdtId(1, 20), CrdtId(1, 15), END_MARKER, TextValue::Text("milk\neggs".into())),
+            item(CrdtId(1, 10), END_MARKER, CrdtId(1, 20), TextValue::Text("Todo\nbuy ".into())),
+        ];
+        let styles = HashMap::from([
+            (END_MARKER, ParagraphStyle::Heading),
+            (CrdtId(1, 14), ParagraphStyle::Bullet),
+            (CrdtId(1, 24), ParagraphStyle::Bullet),
+        ]);
+        let text = Text::from_items(items, &styles);
+        assert_eq!(text.to_plain(), "Todo\nbuy milk\neggs");
+        assert_eq!(text.to_markdown(), "# Todo\n- buy milk\n- eggs\n");
+    }
+
+    #[test]
+    fn it_skips_deleted_characters() {
+        let items = vec![
+            item(CrdtId(1, 10), END_MARKER, CrdtId(1, 13), TextValue::Text("abc".into())),
+            SequenceItem { id: CrdtId(1, 13), left: CrdtId(1, 12), right: CrdtId(1, 15), deleted_length: 2, value: None },
+            item(CrdtId(1, 15), CrdtId(1, 14), END_MARKER, TextValue::Text("d".into())),
+        ];
+        assert_eq!(Text::from_items(items, &HashMap::new()).to_plain(), "abcd");
+    }
+
+    #[test]
+    fn it_applies_inline_formatting() {
+        let items = vec![
+            item(CrdtId(1, 10), END_MARKER, CrdtId(1, 13), TextValue::Text("an ".into())),
+            item(CrdtId(1, 13), CrdtId(1, 12), CrdtId(1, 14), TextValue::Format(FORMAT_BOLD_START)),
+            item(CrdtId(1, 14), CrdtId(1, 13), CrdtId(1, 18), TextValue::Text("bold".into())),
+            item(CrdtId(1, 18), CrdtId(1, 17), CrdtId(1, 19), TextValue::Format(FORMAT_BOLD_END)),
+            item(CrdtId(1, 19), CrdtId(1, 18), END_MARKER, TextValue::Text(" word".into())),
+        ];
+        let text = Text::from_items(items, &HashMap::new());
+        assert_eq!(text.to_plain(), "an bold word");
+        assert_eq!(text.to_markdown(), "an **bold** word\n");
+    }
+}
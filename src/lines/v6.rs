@@ -0,0 +1,712 @@
+//! Parser for version 6 lines files, as written by firmware 3.x.
+//!
+//! Version 6 files are a stream of typed blocks, each describing a part of a scene tree:
+//! tree nodes (groups, which are used as layers), scene items placing groups and lines
+//! into their parent groups, and root text. Items of a group form a CRDT sequence,
+//! ordered by references to their left and right neighbours instead of their position in the file.
+//!
+//! The format is documented by [rmscene](https://github.com/ricklupton/rmscene).
+
+use super::text::{ParagraphStyle, Text, TextValue};
+use super::{Error, Layer, Line, Page, Point, Result};
+use std::collections::{HashMap, HashSet};
+
+pub const HEADER: &[u8] = b"reMarkable .lines file, version=6";
+
+/// Version 6 coordinates are relative to the horizontal center of the page.
+const X_OFFSET: f32 = 1404. / 2.;
+
+const BLOCK_SCENE_TREE: u8 = 0x01;
+const BLOCK_TREE_NODE: u8 = 0x02;
+const BLOCK_SCENE_GROUP_ITEM: u8 = 0x04;
+const BLOCK_SCENE_LINE_ITEM: u8 = 0x05;
+const BLOCK_ROOT_TEXT: u8 = 0x07;
+
+const TAG_BYTE1: u8 = 0x1;
+const TAG_BYTE4: u8 = 0x4;
+const TAG_BYTE8: u8 = 0x8;
+const TAG_LENGTH4: u8 = 0xC;
+const TAG_ID: u8 = 0xF;
+
+/// Identifies nodes and items in the scene tree, made up of an author and a counter.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CrdtId(pub u8, pub u64);
+
+/// Marks the start or end of a sequence in left and right references.
+pub const END_MARKER: CrdtId = CrdtId(0, 0);
+/// The root group of every page.
+pub const ROOT: CrdtId = CrdtId(0, 1);
+
+/// An element of a CRDT sequence, `value` is `None` for deleted items.
+#[derive(Debug, Clone)]
+pub struct SequenceItem<T> {
+    pub id: CrdtId,
+    pub left: CrdtId,
+    pub right: CrdtId,
+    pub deleted_length: u32,
+    pub value: Option<T>,
+}
+
+/// Blocks of a version 6 file, as far as we need them.
+#[derive(Debug, Default)]
+struct Scene {
+    parents: HashMap<CrdtId, CrdtId>,
+    labels: HashMap<CrdtId, String>,
+    groups: HashMap<CrdtId, Vec<SequenceItem<CrdtId>>>,
+    lines: HashMap<CrdtId, Vec<SequenceItem<Line>>>,
+    text: Option<Text>,
+}
+
+/// Parses the blocks following the header of a version 6 lines file into a single page.
+pub fn parse(data: &[u8]) -> Result<Page> {
+    let mut reader = Reader::new(data, 0);
+    let mut scene = Scene::default();
+
+    while !reader.is_empty() {
+        let length = reader.u32()? as usize;
+        let _unknown = reader.u8()?;
+        let _min_version = reader.u8()?;
+        let version = reader.u8()?;
+        let block_type = reader.u8()?;
+        let mut block = reader.take(length)?;
+
+        match block_type {
+            BLOCK_SCENE_TREE => {
+                let id = block.tagged_id(1)?;
+                let _node_id = block.tagged_id(2)?;
+                let _is_update = block.tagged_bool(3)?;
+                let parent = block.subblock(4)?.tagged_id(1)?;
+                scene.parents.insert(id, parent);
+            }
+            BLOCK_TREE_NODE => {
+                let id = block.tagged_id(1)?;
+                let label = block.lww_string(2)?;
+                scene.labels.insert(id, label);
+            }
+            BLOCK_SCENE_GROUP_ITEM => {
+                let (parent, item) = block.scene_item(|value| value.tagged_id(2))?;
+                if let Some(node) = item.value {
+                    scene.parents.insert(node, parent);
+                }
+                scene.groups.entry(parent).or_default().push(item);
+            }
+            BLOCK_SCENE_LINE_ITEM => {
+                let (parent, item) = block.scene_item(|value| value.line(version))?;
+                scene.lines.entry(parent).or_default().push(item);
+            }
+            BLOCK_ROOT_TEXT => {
+                scene.text = Some(block.root_text()?);
+            }
+            _ => {}
+        }
+    }
+
+    Ok(scene.into_page())
+}
+
+impl Scene {
+    /// Collects the lines of every group below the root into one layer per child of the root.
+    fn into_page(mut self) -> Page {
+        let mut layers: Vec<(CrdtId, Layer)> = toposort(self.groups.remove(&ROOT).unwrap_or_default())
+            .into_iter()
+            .filter_map(|item| item.value)
+            .map(|id| (id, Layer { name: self.labels.remove(&id), lines: Vec::new() }))
+            .collect();
+
+        let mut parents: Vec<CrdtId> = self.lines.keys().copied().collect();
+        parents.sort();
+        for parent in parents {
+            let layer_id = self.layer_of(parent);
+            let lines = toposort(self.lines.remove(&parent).unwrap_or_default())
+                .into_iter()
+                .filter_map(|item| item.value);
+            match layers.iter_mut().find(|(id, _)| *id == layer_id) {
+                Some((_, layer)) => layer.lines.extend(lines),
+                None => layers.push((layer_id, Layer {
+                    name: self.labels.get(&layer_id).cloned(),
+                    lines: lines.collect(),
+                })),
+            }
+        }
+
+        Page {
+            layers: layers.into_iter().map(|(_, layer)| layer).collect(),
+            text: self.text,
+        }
+    }
+
+    /// Walks up the tree to the ancestor of `node` which is a direct child of the root.
+    fn layer_of(&self, mut node: CrdtId) -> CrdtId {
+        let mut seen = HashSet::new();
+        while let Some(&parent) = self.parents.get(&node) {
+            if parent == ROOT || !seen.insert(node) {
+                break;
+            }
+            node = parent;
+        }
+        node
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Key {
+    Start,
+    Item(CrdtId),
+    End,
+}
+
+/// Orders the items of a CRDT sequence by their left and right references.
+///
+/// Items which become ready at the same time are ordered by their id, like xochitl does.
+pub fn toposort<T>(items: Vec<SequenceItem<T>>) -> Vec<SequenceItem<T>> {
+    let side = |id: CrdtId, marker: Key| if id == END_MARKER { marker } else { Key::Item(id) };
+
+    // every key must come after all of its dependencies
+    let mut dependencies: HashMap<Key, HashSet<Key>> = HashMap::new();
+    for item in &items {
+        dependencies.entry(Key::Item(item.id)).or_default().insert(side(item.left, Key::Start));
+        dependencies.entry(side(item.right, Key::End)).or_default().insert(Key::Item(item.id));
+    }
+    let mut dependents: HashMap<Key, Vec<Key>> = HashMap::new();
+    for (key, after) in &dependencies {
+        for dependency in after {
+            dependents.entry(*dependency).or_default().push(*key);
+        }
+    }
+    let mut pending: HashMap<Key, usize> = dependencies.iter().map(|(key, after)| (*key, after.len())).collect();
+    for key in dependents.keys() {
+        pending.entry(*key).or_insert(0);
+    }
+
+    let mut items: HashMap<CrdtId, SequenceItem<T>> = items.into_iter().map(|item| (item.id, item)).collect();
+    let mut sorted = Vec::with_capacity(items.len());
+    let mut ready: Vec<Key> = pending.iter().filter(|(_, count)| **count == 0).map(|(key, _)| *key).collect();
+    while !ready.is_empty() {
+        ready.sort();
+        let mut next = Vec::new();
+        for key in &ready {
+            if let Key::Item(id) = key {
+                sorted.extend(items.remove(id));
+            }
+            for dependent in dependents.get(key).into_iter().flatten() {
+                let count = pending.get_mut(dependent).expect("dependent without pending count");
+                *count -= 1;
+                if *count == 0 {
+                    next.push(*dependent);
+                }
+            }
+        }
+        ready = next;
+    }
+
+    // items in a cycle can not be ordered, keep them instead of losing ink
+    let mut remaining: Vec<SequenceItem<T>> = items.into_values().collect();
+    remaining.sort_by_key(|item| item.id);
+    sorted.extend(remaining);
+    sorted
+}
+
+/// A cursor over a block, or a part of it.
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+    /// Offset of `data` in the file, for error messages.
+    base: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(data: &'a [u8], base: usize) -> Self {
+        Self { data, position: 0, base }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    fn offset(&self) -> usize {
+        self.base + self.position
+    }
+
+    pub(crate) fn take(&mut self, length: usize) -> Result<Reader<'a>> {
+        let end = self.position.checked_add(length)
+            .filter(|end| *end <= self.data.len())
+            .ok_or(Error::UnexpectedEof { offset: self.offset() })?;
+        let reader = Reader::new(&self.data[self.position..end], self.offset());
+        self.position = end;
+        Ok(reader)
+    }
+
+    fn bytes<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut bytes = [0; N];
+        bytes.copy_from_slice(self.take(N)?.data);
+        Ok(bytes)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes::<1>()?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.bytes()?))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes()?))
+    }
+
+    pub(crate) fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.bytes()?))
+    }
+
+    pub(crate) fn f64(&mut self) -> Result<f64> {
+        Ok(f64::from_le_bytes(self.bytes()?))
+    }
+
+    /// Reads an unsigned LEB128 integer.
+    pub(crate) fn varuint(&mut self) -> Result<u64> {
+        let mut result = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            result |= u64::from(byte & 0x7f).checked_shl(shift).unwrap_or(0);
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+            shift += 7;
+        }
+    }
+
+    pub(crate) fn id(&mut self) -> Result<CrdtId> {
+        Ok(CrdtId(self.u8()?, self.varuint()?))
+    }
+
+    /// Checks whether the next tag has the given index and type, without consuming it.
+    pub(crate) fn has_tag(&self, index: u32, tag_type: u8) -> bool {
+        let mut peek = Reader::new(self.data, self.base);
+        peek.position = self.position;
+        !peek.is_empty() && peek.varuint().is_ok_and(|tag| tag == (u64::from(index) << 4 | u64::from(tag_type)))
+    }
+
+    fn tag(&mut self, index: u32, tag_type: u8) -> Result<()> {
+        if !self.has_tag(index, tag_type) {
+            return Err(Error::UnexpectedTag { index, tag_type, offset: self.offset() });
+        }
+        self.varuint()?;
+        Ok(())
+    }
+
+    pub(crate) fn tagged_id(&mut self, index: u32) -> Result<CrdtId> {
+        self.tag(index, TAG_ID)?;
+        self.id()
+    }
+
+    pub(crate) fn tagged_bool(&mut self, index: u32) -> Result<bool> {
+        self.tag(index, TAG_BYTE1)?;
+        Ok(self.u8()? != 0)
+    }
+
+    pub(crate) fn tagged_u32(&mut self, index: u32) -> Result<u32> {
+        self.tag(index, TAG_BYTE4)?;
+        self.u32()
+    }
+
+    pub(crate) fn tagged_f32(&mut self, index: u32) -> Result<f32> {
+        self.tag(index, TAG_BYTE4)?;
+        self.f32()
+    }
+
+    pub(crate) fn tagged_f64(&mut self, index: u32) -> Result<f64> {
+        self.tag(index, TAG_BYTE8)?;
+        self.f64()
+    }
+
+    pub(crate) fn has_subblock(&self, index: u32) -> bool {
+        self.has_tag(index, TAG_LENGTH4)
+    }
+
+    pub(crate) fn subblock(&mut self, index: u32) -> Result<Reader<'a>> {
+        self.tag(index, TAG_LENGTH4)?;
+        let length = self.u32()? as usize;
+        self.take(length)
+    }
+
+    /// Reads a string, prefixed by its length and an "is ascii" flag.
+    pub(crate) fn string(&mut self) -> Result<String> {
+        let length = self.varuint()? as usize;
+        let _is_ascii = self.u8()?;
+        let offset = self.offset();
+        let bytes = self.take(length)?;
+        String::from_utf8(bytes.data.to_vec()).map_err(|_| Error::InvalidString { offset })
+    }
+
+    /// Reads a last-write-wins register holding a string, dropping its timestamp.
+    pub(crate) fn lww_string(&mut self, index: u32) -> Result<String> {
+        let mut register = self.subblock(index)?;
+        let _timestamp = register.tagged_id(1)?;
+        register.subblock(2)?.string()
+    }
+
+    /// Reads a string, which may be followed by an inline formatting code instead.
+    fn string_with_format(&mut self, index: u32) -> Result<TextValue> {
+        let mut block = self.subblock(index)?;
+        let text = block.string()?;
+        if block.has_tag(2, TAG_BYTE4) {
+            return Ok(TextValue::Format(block.tagged_u32(2)?));
+        }
+        Ok(TextValue::Text(text))
+    }
+
+    fn root_text(&mut self) -> Result<Text> {
+        let _id = self.tagged_id(1)?;
+        let mut content = self.subblock(2)?;
+
+        let mut items = Vec::new();
+        let mut sequence = content.subblock(1)?.subblock(1)?;
+        for _ in 0..sequence.varuint()? {
+            let mut item = sequence.subblock(0)?;
+            let id = item.tagged_id(2)?;
+            let left = item.tagged_id(3)?;
+            let right = item.tagged_id(4)?;
+            let deleted_length = item.tagged_u32(5)?;
+            let value = if item.has_subblock(6) {
+                item.string_with_format(6)?
+            } else {
+                TextValue::Text(String::new())
+            };
+            items.push(SequenceItem { id, left, right, deleted_length, value: Some(value) });
+        }
+
+        let mut styles = HashMap::new();
+        let mut formats = content.subblock(2)?.subblock(1)?;
+        for _ in 0..formats.varuint()? {
+            let id = formats.id()?;
+            let _timestamp = formats.tagged_id(1)?;
+            let mut style = formats.subblock(2)?;
+            let _unknown = style.u8()?;
+            styles.insert(id, ParagraphStyle::from(style.u8()?));
+        }
+
+        Ok(Text::from_items(items, &styles))
+    }
+
+    /// Reads the header of a scene item and its value, if it has not been deleted.
+    fn scene_item<T>(&mut self, value: impl FnOnce(&mut Reader<'a>) -> Result<T>) -> Result<(CrdtId, SequenceItem<T>)> {
+        let parent = self.tagged_id(1)?;
+        let id = self.tagged_id(2)?;
+        let left = self.tagged_id(3)?;
+        let right = self.tagged_id(4)?;
+        let deleted_length = self.tagged_u32(5)?;
+        let value = if self.has_subblock(6) {
+            let mut block = self.subblock(6)?;
+            let _item_type = block.u8()?;
+            Some(value(&mut block)?)
+        } else {
+            None
+        };
+        Ok((parent, SequenceItem { id, left, right, deleted_length, value }))
+    }
+
+    fn line(&mut self, version: u8) -> Result<Line> {
+        let brush_type = self.tagged_u32(1)? as i32;
+        let color = self.tagged_u32(2)? as i32;
+        let thickness_scale = self.tagged_f64(3)?;
+        let _starting_length = self.tagged_f32(4)?;
+        let mut data = self.subblock(5)?;
+        let mut points = Vec::new();
+        while !data.is_empty() {
+            points.push(data.point(version)?);
+        }
+        Ok(Line {
+            brush_type: brush_type.into(),
+            color: color.into(),
+            brush_base_size: thickness_scale as f32,
+            points,
+        })
+    }
+
+    fn point(&mut self, version: u8) -> Result<Point> {
+        let x = self.f32()? + X_OFFSET;
+        let y = self.f32()?;
+        if version == 1 {
+            return Ok(Point {
+                x,
+                y,
+                speed: self.f32()?,
+                direction: self.f32()?,
+                width: self.f32()?,
+                pressure: self.f32()?,
+            });
+        }
+        // newer files quantize everything but the position
+        let speed = f32::from(self.u16()?) / 4.;
+        let width = f32::from(self.u16()?) / 4.;
+        let direction = f32::from(self.u8()?) * std::f32::consts::TAU / 255.;
+        let pressure = f32::from(self.u8()?) / 255.;
+        Ok(Point { x, y, speed, direction, width, pressure })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::lines::{BrushType, Color};
+
+    /// Writes blocks of a version 6 file, for building test fixtures.
+    #[derive(Default)]
+    pub(crate) struct Writer(pub Vec<u8>);
+
+    /// Where a scene item sits: its parent, its own id and its neighbours in the parent's sequence.
+    #[derive(Clone, Copy)]
+    pub(crate) struct ItemIds {
+        pub parent: CrdtId,
+        pub id: CrdtId,
+        pub left: CrdtId,
+        pub right: CrdtId,
+    }
+
+    impl Writer {
+        pub(crate) fn varuint(&mut self, mut value: u64) -> &mut Self {
+            loop {
+                let byte = (value & 0x7f) as u8;
+                value >>= 7;
+                if value == 0 {
+                    self.0.push(byte);
+                    return self;
+                }
+                self.0.push(byte | 0x80);
+            }
+        }
+
+        pub(crate) fn tag(&mut self, index: u32, tag_type: u8) -> &mut Self {
+            self.varuint(u64::from(index) << 4 | u64::from(tag_type))
+        }
+
+        pub(crate) fn id(&mut self, index: u32, id: CrdtId) -> &mut Self {
+            self.tag(index, TAG_ID);
+            self.0.push(id.0);
+            self.varuint(id.1)
+        }
+
+        pub(crate) fn u8(&mut self, index: u32, value: u8) -> &mut Self {
+            self.tag(index, TAG_BYTE1);
+            self.0.push(value);
+            self
+        }
+
+        pub(crate) fn u32(&mut self, index: u32, value: u32) -> &mut Self {
+            self.tag(index, TAG_BYTE4);
+            self.0.extend(value.to_le_bytes());
+            self
+        }
+
+        pub(crate) fn f32(&mut self, index: u32, value: f32) -> &mut Self {
+            self.tag(index, TAG_BYTE4);
+            self.0.extend(value.to_le_bytes());
+            self
+        }
+
+        pub(crate) fn f64(&mut self, index: u32, value: f64) -> &mut Self {
+            self.tag(index, TAG_BYTE8);
+            self.0.extend(value.to_le_bytes());
+            self
+        }
+
+        pub(crate) fn subblock(&mut self, index: u32, content: &Writer) -> &mut Self {
+            self.tag(index, TAG_LENGTH4);
+            self.0.extend((content.0.len() as u32).to_le_bytes());
+            self.0.extend(&content.0);
+            self
+        }
+
+        pub(crate) fn string(&mut self, value: &str) -> &mut Self {
+            self.varuint(value.len() as u64);
+            self.0.push(1);
+            self.0.extend(value.as_bytes());
+            self
+        }
+
+        pub(crate) fn lww_string(&mut self, index: u32, value: &str) -> &mut Self {
+            let mut string = Writer::default();
+            string.string(value);
+            let mut register = Writer::default();
+            register.id(1, CrdtId(1, 1)).subblock(2, &string);
+            self.subblock(index, &register)
+        }
+
+        pub(crate) fn block(&mut self, block_type: u8, version: u8, content: &Writer) -> &mut Self {
+            self.0.extend((content.0.len() as u32).to_le_bytes());
+            self.0.extend([0, 1, version, block_type]);
+            self.0.extend(&content.0);
+            self
+        }
+
+        pub(crate) fn scene_item(&mut self, block_type: u8, version: u8, ids: ItemIds, value: Option<(u8, &Writer)>) -> &mut Self {
+            let mut item = Writer::default();
+            item.id(1, ids.parent).id(2, ids.id).id(3, ids.left).id(4, ids.right);
+            match value {
+                Some((item_type, value)) => {
+                    let mut content = Writer::default();
+                    content.0.push(item_type);
+                    content.0.extend(&value.0);
+                    item.u32(5, 0).subblock(6, &content);
+                }
+                None => {
+                    item.u32(5, 1);
+                }
+            }
+            self.block(block_type, version, &item)
+        }
+
+        pub(crate) fn layer(&mut self, node: CrdtId, label: &str, left: CrdtId, right: CrdtId) -> &mut Self {
+            let mut tree = Writer::default();
+            let mut parent = Writer::default();
+            parent.id(1, ROOT);
+            tree.id(1, node).id(2, END_MARKER).u8(3, 1).subblock(4, &parent);
+            self.block(BLOCK_SCENE_TREE, 1, &tree);
+
+            let mut node_block = Writer::default();
+            node_block.id(1, node).lww_string(2, label);
+            self.block(BLOCK_TREE_NODE, 1, &node_block);
+
+            let mut value = Writer::default();
+            value.id(2, node);
+            let ids = ItemIds { parent: ROOT, id: CrdtId(node.0, node.1 + 1000), left, right };
+            self.scene_item(BLOCK_SCENE_GROUP_ITEM, 1, ids, Some((2, &value)))
+        }
+
+        pub(crate) fn line(&mut self, parent: CrdtId, id: CrdtId, left: CrdtId, right: CrdtId, points: &[(f32, f32)]) -> &mut Self {
+            let mut data = Writer::default();
+            for (x, y) in points {
+                data.0.extend(x.to_le_bytes());
+                data.0.extend(y.to_le_bytes());
+                data.0.extend(8u16.to_le_bytes());
+                data.0.extend(12u16.to_le_bytes());
+                data.0.extend([0, 255]);
+            }
+            let mut value = Writer::default();
+            value.u32(1, 17).u32(2, 6).f64(3, 2.0).f32(4, 0.).subblock(5, &data).id(6, END_MARKER);
+            self.scene_item(BLOCK_SCENE_LINE_ITEM, 2, ItemIds { parent, id, left, right }, Some((3, &value)))
+        }
+    }
+
+    #[test]
+    fn it_parses_root_text() -> Result<()> {
+        let mut string = Writer::default();
+        string.string("Title\nbody");
+        let mut item = Writer::default();
+        item.id(2, CrdtId(1, 16)).id(3, END_MARKER).id(4, END_MARKER).u32(5, 0).subblock(6, &string);
+        let mut sequence = Writer::default();
+        sequence.varuint(1).subblock(0, &item);
+        let mut text_items = Writer::default();
+        text_items.subblock(1, &sequence);
+
+        let mut style = Writer::default();
+        style.0.extend([17, 2]);
+        let mut formats = Writer::default();
+        formats.varuint(1);
+        formats.0.extend([0, 0]);
+        formats.id(1, CrdtId(1, 15)).subblock(2, &style);
+        let mut text_formats = Writer::default();
+        text_formats.subblock(1, &formats);
+
+        let mut content = Writer::default();
+        content.subblock(1, &text_items).subblock(2, &text_formats);
+        let mut position = Writer::default();
+        position.0.extend((-468f64).to_le_bytes());
+        position.0.extend(234f64.to_le_bytes());
+        let mut block = Writer::default();
+        block.id(1, END_MARKER).subblock(2, &content).subblock(3, &position).f32(4, 936.);
+        let mut file = Writer::default();
+        file.block(BLOCK_ROOT_TEXT, 1, &block);
+
+        let page = parse(&file.0)?;
+        let text = page.text.expect("page without text");
+        assert_eq!(text.to_markdown(), "# Title\nbody\n");
+        Ok(())
+    }
+
+    #[test]
+    fn it_parses_lines_into_layers() -> Result<()> {
+        let layer = CrdtId(0, 11);
+        let mut file = Writer::default();
+        file.layer(layer, "Sketch", END_MARKER, END_MARKER)
+            .line(layer, CrdtId(1, 20), END_MARKER, END_MARKER, &[(-702., 10.), (0., 20.)]);
+
+        let page = parse(&file.0)?;
+        assert_eq!(page.layers.len(), 1);
+        assert_eq!(page.layers[0].name.as_deref(), Some("Sketch"));
+        let line = &page.layers[0].lines[0];
+        assert_eq!(line.brush_type, BrushType::Fineliner);
+        assert_eq!(line.color, Color::Blue);
+        assert_eq!(line.brush_base_size, 2.0);
+        assert_eq!(line.points.len(), 2);
+        assert_eq!(line.points[0].x, 0.);
+        assert_eq!(line.points[1].x, 702.);
+        assert_eq!(line.points[1].y, 20.);
+        assert_eq!(line.points[0].speed, 2.);
+        assert_eq!(line.points[0].width, 3.);
+        assert_eq!(line.points[0].pressure, 1.);
+        Ok(())
+    }
+
+    #[test]
+    fn it_orders_items_by_their_neighbours() -> Result<()> {
+        let (first, second) = (CrdtId(0, 11), CrdtId(0, 12));
+        let mut file = Writer::default();
+        // stored in reverse order, the second layer references the first one on its left
+        file.layer(second, "Second", CrdtId(first.0, first.1 + 1000), END_MARKER)
+            .layer(first, "First", END_MARKER, END_MARKER)
+            .line(first, CrdtId(1, 31), CrdtId(1, 30), END_MARKER, &[(1., 1.)])
+            .line(first, CrdtId(1, 30), END_MARKER, CrdtId(1, 31), &[(0., 0.)])
+            .line(second, CrdtId(1, 40), END_MARKER, END_MARKER, &[(2., 2.)]);
+
+        let page = parse(&file.0)?;
+        let names: Vec<_> = page.layers.iter().map(|l| l.name.as_deref()).collect();
+        assert_eq!(names, [Some("First"), Some("Second")]);
+        let xs: Vec<_> = page.layers[0].lines.iter().map(|l| l.points[0].x - X_OFFSET).collect();
+        assert_eq!(xs, [0., 1.]);
+        assert_eq!(page.layers[1].lines.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn it_skips_deleted_and_unknown_items() -> Result<()> {
+        let layer = CrdtId(0, 11);
+        let mut file = Writer::default();
+        file.layer(layer, "Layer 1", END_MARKER, END_MARKER)
+            .scene_item(BLOCK_SCENE_LINE_ITEM, 2, ItemIds { parent: layer, id: CrdtId(1, 20), left: END_MARKER, right: END_MARKER }, None)
+            .block(0x09, 1, Writer::default().u32(1, 42));
+
+        let page = parse(&file.0)?;
+        assert_eq!(page.layers.len(), 1);
+        assert!(page.layers[0].lines.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn it_fails_on_truncated_blocks() {
+        let mut file = Writer::default();
+        file.layer(CrdtId(0, 11), "Layer 1", END_MARKER, END_MARKER);
+        file.0.truncate(file.0.len() - 3);
+        assert!(matches!(parse(&file.0), Err(Error::UnexpectedEof { .. })));
+    }
+
+    #[test]
+    fn it_detects_the_version_from_the_header() -> Result<()> {
+        let mut data = format!("{:<width$}", std::str::from_utf8(HEADER).unwrap(), width = crate::lines::HEADER_LENGTH).into_bytes();
+        let mut file = Writer::default();
+        file.layer(CrdtId(0, 11), "Layer 1", END_MARKER, END_MARKER);
+        data.extend(file.0);
+        let pages = crate::lines::parse(&mut data.as_slice())?;
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].layers[0].name.as_deref(), Some("Layer 1"));
+        Ok(())
+    }
+
+    #[test]
+    fn it_fails_on_truncated_headers() {
+        let data = format!("{:<40}", std::str::from_utf8(HEADER).unwrap()).into_bytes();
+        let parsed = crate::lines::parse(&mut data.as_slice());
+        assert!(matches!(parsed, Err(Error::UnexpectedEof { offset: 40 })));
+    }
+}
@@ -0,0 +1,73 @@
+//! Screen geometry of the tablets, which page coordinates are relative to.
+
+use serde::{Deserialize, Serialize};
+use super::{WIDTH, HEIGHT};
+
+/// The tablet a document was written on, set with `device` in the config.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DeviceProfile {
+    Remarkable1,
+    #[default]
+    Remarkable2,
+    PaperPro,
+}
+
+impl DeviceProfile {
+    /// Width and height of the screen in pixels, held upright.
+    pub fn size(self) -> (f32, f32) {
+        match self {
+            Self::Remarkable1 | Self::Remarkable2 => (WIDTH, HEIGHT),
+            Self::PaperPro => (1620., 2160.),
+        }
+    }
+
+    /// Resolution of the screen.
+    pub fn dpi(self) -> f32 {
+        match self {
+            Self::Remarkable1 | Self::Remarkable2 => 226.,
+            Self::PaperPro => 229.,
+        }
+    }
+
+    /// Scale that renders pages at a resolution of `dpi`, one pixel per pixel of the screen being `1.0`.
+    pub fn scale_at(self, dpi: f32) -> f32 {
+        dpi / self.dpi()
+    }
+
+    /// Whether the screen shows colours. Others show coloured lines in grey, as previews of their pages
+    /// render them with the [greyscale palette](super::Palette::greyscale).
+    pub fn is_color(self) -> bool {
+        self == Self::PaperPro
+    }
+
+    /// Left, top, right and bottom edge of the screen in page coordinates.
+    ///
+    /// Those are laid out for 1404 pixel wide screens, as version 6 files count from the horizontal center,
+    /// wider screens extend to both sides.
+    pub fn bounds(self) -> [f32; 4] {
+        let (width, height) = self.size();
+        let left = (WIDTH - width) / 2.;
+        [left, 0., left + width, height]
+    }
+
+    /// Transform from template coordinates, made for 1404x1872 screens, to page coordinates filling the screen.
+    pub fn template_transform(self) -> [f32; 6] {
+        let scale = self.size().0 / WIDTH;
+        [scale, 0., 0., scale, self.bounds()[0], 0.]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_centers_wider_screens_on_the_page() {
+        assert_eq!(DeviceProfile::Remarkable2.bounds(), [0., 0., WIDTH, HEIGHT]);
+        assert_eq!(DeviceProfile::PaperPro.bounds(), [-108., 0., 1512., 2160.]);
+        // templates keep their aspect ratio
+        let [scale, _, _, _, left, _] = DeviceProfile::PaperPro.template_transform();
+        assert_eq!((left + WIDTH * scale, HEIGHT * scale), (1512., 2160.));
+    }
+}
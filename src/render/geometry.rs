@@ -0,0 +1,175 @@
+//! Passes over the points of lines between parsing and rendering, to shrink output and smooth strokes.
+//!
+//! Raw lines hold a point per sample of the pen, most of which add nothing visible:
+//! duplicate points are dropped first, then [Ramer–Douglas–Peucker](https://en.wikipedia.org/wiki/Ramer%E2%80%93Douglas%E2%80%93Peucker_algorithm)
+//! drops points within `tolerance` of the simplified line, and [Catmull-Rom](https://en.wikipedia.org/wiki/Centripetal_Catmull%E2%80%93Rom_spline)
+//! smoothing optionally rounds the corners left behind.
+
+use serde::{Deserialize, Serialize};
+use crate::lines::{Line, Page, Point};
+
+/// Points closer than this, in pixels, count as duplicates.
+const DUPLICATE_DISTANCE: f32 = 0.01;
+
+/// Settings of the pipeline, overridable in the `[geometry]` section of the config.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Geometry {
+    /// Drop points at the position of the point before them.
+    pub dedup: bool,
+    /// How far, in pixels, simplified lines may stray from the original points, `0` keeps all points.
+    /// Changes of the line's width count as well, by half.
+    pub tolerance: f32,
+    /// Number of points interpolated between two points, `0` keeps straight segments.
+    pub smoothing: u32,
+}
+
+impl Default for Geometry {
+    fn default() -> Self {
+        Self { dedup: true, tolerance: 0.5, smoothing: 0 }
+    }
+}
+
+impl Geometry {
+    /// Keeps lines as they are.
+    pub const RAW: Self = Self { dedup: false, tolerance: 0., smoothing: 0 };
+
+    /// Returns a copy of `page` with all passes applied to its lines.
+    pub fn apply(&self, page: &Page) -> Page {
+        let mut page = page.clone();
+        for line in page.layers.iter_mut().flat_map(|layer| &mut layer.lines) {
+            self.apply_line(line);
+        }
+        page
+    }
+
+    fn apply_line(&self, line: &mut Line) {
+        if self.dedup {
+            line.points.dedup_by(|point, previous| distance(point, previous) < DUPLICATE_DISTANCE);
+        }
+        if self.tolerance > 0. {
+            line.points = simplify(&line.points, self.tolerance);
+        }
+        if self.smoothing > 0 {
+            line.points = smooth(&line.points, self.smoothing);
+        }
+    }
+}
+
+fn distance(a: &Point, b: &Point) -> f32 {
+    (a.x - b.x).hypot(a.y - b.y)
+}
+
+/// How far `point` is from the segment between `start` and `end`, including the difference to their interpolated half width.
+fn deviation(point: &Point, start: &Point, end: &Point) -> f32 {
+    let (dx, dy) = (end.x - start.x, end.y - start.y);
+    let length = dx * dx + dy * dy;
+    let t = match length > 0. {
+        true => (((point.x - start.x) * dx + (point.y - start.y) * dy) / length).clamp(0., 1.),
+        false => 0.,
+    };
+    let (x, y) = (start.x + t * dx, start.y + t * dy);
+    let width = start.width + t * (end.width - start.width);
+    (point.x - x).hypot(point.y - y).max((point.width - width).abs() / 2.)
+}
+
+/// Ramer–Douglas–Peucker, without recursion as lines can have thousands of points.
+fn simplify(points: &[Point], tolerance: f32) -> Vec<Point> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+    let mut ranges = vec![(0, points.len() - 1)];
+    while let Some((start, end)) = ranges.pop() {
+        let farthest = (start + 1..end)
+            .map(|index| (index, deviation(&points[index], &points[start], &points[end])))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((index, deviation)) = farthest {
+            if deviation > tolerance {
+                keep[index] = true;
+                ranges.push((start, index));
+                ranges.push((index, end));
+            }
+        }
+    }
+    points.iter().zip(keep).filter(|(_, keep)| *keep).map(|(point, _)| *point).collect()
+}
+
+/// Inserts `steps` points on a Catmull-Rom spline between each two points, other attributes are interpolated linearly.
+fn smooth(points: &[Point], steps: u32) -> Vec<Point> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    let mut smoothed = Vec::with_capacity(points.len() * (steps as usize + 1));
+    for index in 0..points.len() - 1 {
+        let p0 = &points[index.saturating_sub(1)];
+        let (p1, p2) = (&points[index], &points[index + 1]);
+        let p3 = &points[(index + 2).min(points.len() - 1)];
+        smoothed.push(*p1);
+        for step in 1..=steps {
+            let t = step as f32 / (steps + 1) as f32;
+            let spline = |a: f32, b: f32, c: f32, d: f32| {
+                0.5 * (2. * b + (c - a) * t + (2. * a - 5. * b + 4. * c - d) * t * t + (3. * b - a - 3. * c + d) * t * t * t)
+            };
+            let lerp = |a: f32, b: f32| a + (b - a) * t;
+            smoothed.push(Point {
+                x: spline(p0.x, p1.x, p2.x, p3.x),
+                y: spline(p0.y, p1.y, p2.y, p3.y),
+                speed: lerp(p1.speed, p2.speed),
+                direction: lerp(p1.direction, p2.direction),
+                width: lerp(p1.width, p2.width),
+                pressure: lerp(p1.pressure, p2.pressure),
+            });
+        }
+    }
+    smoothed.extend(points.last());
+    smoothed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lines::{BrushType, Color, Layer};
+
+    fn point(x: f32, y: f32) -> Point {
+        Point { x, y, speed: 0., direction: 0., width: 2., pressure: 1. }
+    }
+
+    fn page(points: Vec<Point>) -> Page {
+        let line = Line { brush_type: BrushType::Fineliner, color: Color::Black, brush_base_size: 2., points };
+        Page { layers: vec![Layer { name: None, lines: vec![line] }], text: None }
+    }
+
+    fn points(page: &Page) -> Vec<(f32, f32)> {
+        page.layers[0].lines[0].points.iter().map(|p| (p.x, p.y)).collect()
+    }
+
+    #[test]
+    fn it_drops_duplicate_and_collinear_points() {
+        let line = page(vec![point(0., 0.), point(0., 0.), point(10., 0.2), point(20., 0.), point(20., 30.)]);
+        let dedup = Geometry { tolerance: 0., ..Geometry::default() };
+        assert_eq!(points(&dedup.apply(&line)).len(), 4);
+        assert_eq!(points(&Geometry::default().apply(&line)), [(0., 0.), (20., 0.), (20., 30.)]);
+        assert_eq!(points(&Geometry::RAW.apply(&line)).len(), 5);
+    }
+
+    #[test]
+    fn it_keeps_changes_of_width() {
+        let mut wide = point(10., 0.);
+        wide.width = 8.;
+        let line = page(vec![point(0., 0.), wide, point(20., 0.)]);
+        assert_eq!(points(&Geometry::default().apply(&line)).len(), 3);
+    }
+
+    #[test]
+    fn it_smooths_through_the_original_points() {
+        let line = page(vec![point(0., 0.), point(10., 10.), point(20., 0.)]);
+        let smooth = Geometry { smoothing: 3, ..Geometry::RAW };
+        let smoothed = points(&smooth.apply(&line));
+        assert_eq!(smoothed.len(), 3 + 2 * 3);
+        assert_eq!([smoothed[0], smoothed[4], smoothed[8]], [(0., 0.), (10., 10.), (20., 0.)]);
+        assert!(smoothed[3].1 > 7.5, "bulges towards the corner");
+    }
+}
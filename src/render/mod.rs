@@ -0,0 +1,436 @@
+//! # Render
+//!
+//! Backends turning [pages](crate::lines::Page) into files, behind the [`Renderer`] trait.
+//! They share their [`RenderOptions`] and map pages to the output through a [`Viewport`].
+
+pub mod device;
+pub mod geometry;
+pub mod palette;
+pub mod pdf;
+pub mod pens;
+pub mod raster;
+pub mod svg;
+pub mod templates;
+pub mod tessellate;
+pub mod xopp;
+
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use snafu::Snafu;
+use crate::lines::{Layer, Page};
+
+#[doc(inline)]
+pub use {device::DeviceProfile, geometry::Geometry, palette::Palette, pdf::{AnnotatedPdfRenderer, PdfRenderer}, raster::PngRenderer, svg::SvgRenderer, templates::Template, xopp::XoppRenderer};
+
+#[derive(Snafu, Debug)]
+#[snafu(visibility(pub(crate)))]
+pub enum Error {
+    #[snafu(display("Unable to write rendered page: {}", source))]
+    Write { source: io::Error },
+    #[snafu(display("Unable to build pdf: {}", source))]
+    Pdf { source: lopdf::Error },
+    #[snafu(display("Unable to render an image of {}x{} pixels", width, height))]
+    ImageSize { width: u32, height: u32 },
+    #[snafu(display("Unable to encode png: {}", message))]
+    EncodePng { message: String },
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+const WIDTH: f32 = 1404.;
+const HEIGHT: f32 = 1872.;
+/// Space left around the outlines of lines when cropping to them, in pixels.
+const INK_MARGIN: f32 = 4.;
+
+/// Returns the transform applying `inner` first and then `outer`, in the order of [`Viewport::transform`].
+pub(crate) fn concat(outer: [f32; 6], inner: [f32; 6]) -> [f32; 6] {
+    let [a0, b0, c0, d0, e0, f0] = outer;
+    let [a, b, c, d, e, f] = inner;
+    [
+        a0 * a + c0 * b,
+        b0 * a + d0 * b,
+        a0 * c + c0 * d,
+        b0 * c + d0 * d,
+        a0 * e + c0 * f + e0,
+        b0 * e + d0 * f + f0,
+    ]
+}
+
+/// Ranges of pages counted from 1, such as `1-5,8` or `3-` for all pages from the third on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageRanges(Vec<RangeInclusive<usize>>);
+
+impl PageRanges {
+    /// Returns the selected pages of a document with `count` pages, counted from 0.
+    /// Pages beyond the end are only kept if explicitly asked for.
+    pub fn indexes(&self, count: usize) -> Vec<usize> {
+        let mut indexes = Vec::new();
+        for range in &self.0 {
+            let end = if *range.end() == usize::MAX { count } else { *range.end() };
+            for page in *range.start()..=end {
+                if !indexes.contains(&(page - 1)) {
+                    indexes.push(page - 1);
+                }
+            }
+        }
+        indexes
+    }
+}
+
+impl FromStr for PageRanges {
+    type Err = String;
+
+    fn from_str(value: &str) -> core::result::Result<Self, Self::Err> {
+        let page = |page: &str| match page.trim().parse::<usize>() {
+            Ok(page) if page > 0 => Ok(page),
+            _ => Err(format!("invalid page {:?}, pages are counted from 1", page)),
+        };
+        value
+            .split(',')
+            .map(|range| match range.split_once('-') {
+                Some((start, end)) if end.trim().is_empty() => Ok(page(start)?..=usize::MAX),
+                Some((start, end)) => match (page(start)?, page(end)?) {
+                    (start, end) if start > end => Err(format!("invalid range {:?}, it ends before it starts", range.trim())),
+                    (start, end) => Ok(start..=end),
+                },
+                None => page(range).map(|page| page..=page),
+            })
+            .collect::<core::result::Result<_, _>>()
+            .map(Self)
+    }
+}
+
+/// Layers to render, by name or by their position counted from 1, such as `Sketch,Final` or `2`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayerSelection(Vec<String>);
+
+impl LayerSelection {
+    /// Returns a copy of `page` with only the selected layers, named as by [`layer_name`] before any are left out.
+    pub fn apply(&self, page: &Page) -> Page {
+        let layers = page.layers
+            .iter()
+            .enumerate()
+            .filter(|(index, layer)| {
+                let name = layer_name(*index, layer);
+                self.0.iter().any(|selected| *selected == name || selected.parse() == Ok(index + 1))
+            })
+            .map(|(index, layer)| Layer { name: Some(layer_name(index, layer)), ..layer.clone() })
+            .collect();
+        Page { layers, ..page.clone() }
+    }
+}
+
+impl FromStr for LayerSelection {
+    type Err = String;
+
+    fn from_str(value: &str) -> core::result::Result<Self, Self::Err> {
+        let layers: Vec<String> = value.split(',').map(str::trim).filter(|l| !l.is_empty()).map(String::from).collect();
+        match layers.is_empty() {
+            true => Err("no layers selected".to_string()),
+            false => Ok(Self(layers)),
+        }
+    }
+}
+
+/// Escapes text for XML, including attributes.
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Name of a layer as the tablet shows it, unnamed layers are numbered like `Layer 2`.
+pub fn layer_name(index: usize, layer: &Layer) -> String {
+    layer.name.clone().unwrap_or_else(|| format!("Layer {}", index + 1))
+}
+
+/// Options shared by all [`Renderer`]s.
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    /// Pages to render, all by default.
+    pub pages: Option<PageRanges>,
+    /// Layers to render, all by default.
+    pub layers: Option<LayerSelection>,
+    /// Simplification and smoothing of lines, applied before rendering.
+    pub geometry: Geometry,
+    pub palette: Palette,
+    /// Template of each page of the document, pages without one are blank.
+    pub templates: Vec<Template>,
+    pub crop: Crop,
+    /// The document's custom zoom, for [`Crop::Zoom`].
+    pub zoom: Option<Zoom>,
+    /// How far each page of the document extends down, for pages scrolled beyond the screen.
+    pub page_heights: Vec<Option<f32>>,
+    /// Orientation of the output, documents default to the one they were written in.
+    pub orientation: Option<Orientation>,
+    pub device: DeviceProfile,
+    /// Size of the output relative to the screen, one unit per pixel at `1.0`.
+    pub scale: f32,
+    /// Describe each line, in formats that allow comments.
+    pub debug_dump: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            pages: None,
+            layers: None,
+            geometry: Geometry::default(),
+            palette: Palette::default(),
+            templates: Vec::new(),
+            crop: Crop::default(),
+            zoom: None,
+            page_heights: Vec::new(),
+            orientation: None,
+            device: DeviceProfile::default(),
+            scale: 1.,
+            debug_dump: false,
+        }
+    }
+}
+
+/// Which part of a page is rendered.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Crop {
+    /// The whole page, extended downwards as far as it was scrolled or written on.
+    #[default]
+    Page,
+    /// Only the area containing ink.
+    Content,
+    /// The area shown at the document's custom zoom, or the whole page without one.
+    Zoom,
+}
+
+/// A zoomed in view, as stored by `customZoomCenterX`, `customZoomCenterY` and `customZoomScale` in `.content`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Zoom {
+    /// Center of the view, horizontally counted from the middle of the page like version 6 lines.
+    pub center: (f32, f32),
+    pub scale: f32,
+}
+
+/// How pages are turned in the output.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    #[default]
+    Portrait,
+    /// Turned a quarter clockwise, as the tablet shows landscape pages.
+    Landscape,
+}
+
+impl Orientation {
+    /// Parses the `orientation` of `.content` files, which is portrait unless stated otherwise.
+    pub fn from_content(orientation: &str) -> Self {
+        match orientation {
+            "landscape" => Self::Landscape,
+            _ => Self::Portrait,
+        }
+    }
+}
+
+/// A backend writing pages in some format.
+///
+/// [`Document::render`](crate::storage::document::Document::render) loads and selects the pages,
+/// so other formats only need to implement this.
+pub trait Renderer {
+    /// Extension of the written files.
+    fn extension(&self) -> &str;
+
+    /// Whether all pages go into a single file, instead of one file per page.
+    fn multi_page(&self) -> bool {
+        false
+    }
+
+    /// Writes `pages`, each with its index in the document, to `output`.
+    ///
+    /// Renderers that aren't [`multi_page`](Renderer::multi_page) get one page at a time.
+    fn render(&self, output: &mut dyn Write, pages: &[(usize, &Page)], options: &RenderOptions) -> Result<()>;
+}
+
+/// The part of a page that is rendered and how it maps to the output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    /// Left, top, right and bottom edge in page coordinates.
+    pub bounds: [f32; 4],
+    pub scale: f32,
+    pub orientation: Orientation,
+    pub device: DeviceProfile,
+}
+
+impl Viewport {
+    /// The part of the page with the given index in the document selected by [`RenderOptions::crop`].
+    pub fn new(index: usize, page: &Page, options: &RenderOptions) -> Self {
+        let orientation = options.orientation.unwrap_or_default();
+        let ink = ink_bounds(page, &options.palette);
+        let [left, top, right, mut bottom] = options.device.bounds();
+        if let Some(height) = options.page_heights.get(index).copied().flatten() {
+            bottom = bottom.max(height);
+        }
+        if let Some(ink) = ink {
+            bottom = bottom.max(ink[3]);
+        }
+        let page_bounds = [left, top, right, bottom];
+
+        let bounds = match options.crop {
+            Crop::Page => page_bounds,
+            Crop::Content => ink.unwrap_or(page_bounds),
+            Crop::Zoom => match options.zoom {
+                Some(zoom) if zoom.scale > 0. => {
+                    let (width, height) = options.device.size();
+                    let (width, height) = match orientation {
+                        Orientation::Portrait => (width, height),
+                        Orientation::Landscape => (height, width),
+                    };
+                    let (x, y) = (WIDTH / 2. + zoom.center.0, zoom.center.1);
+                    let (dx, dy) = (width / zoom.scale / 2., height / zoom.scale / 2.);
+                    [x - dx, y - dy, x + dx, y + dy]
+                }
+                _ => page_bounds,
+            },
+        };
+        Self { bounds, scale: options.scale, orientation, device: options.device }
+    }
+
+    /// Width and height of the output.
+    pub fn size(&self) -> (f32, f32) {
+        let [x0, y0, x1, y1] = self.bounds;
+        let (width, height) = ((x1 - x0) * self.scale, (y1 - y0) * self.scale);
+        match self.orientation {
+            Orientation::Portrait => (width, height),
+            Orientation::Landscape => (height, width),
+        }
+    }
+
+    /// Affine transform `[a, b, c, d, e, f]` from page coordinates to output coordinates,
+    /// both with the origin in the top left corner: `x' = a x + c y + e`, `y' = b x + d y + f`.
+    pub fn transform(&self) -> [f32; 6] {
+        let [x0, y0, _, y1] = self.bounds;
+        let s = self.scale;
+        match self.orientation {
+            Orientation::Portrait => [s, 0., 0., s, -s * x0, -s * y0],
+            Orientation::Landscape => [0., s, -s, 0., s * y1, -s * x0],
+        }
+    }
+
+    /// Transform from template coordinates to output coordinates.
+    pub fn template_transform(&self) -> [f32; 6] {
+        concat(self.transform(), self.device.template_transform())
+    }
+}
+
+/// Left, top, right and bottom edge of the outlines of all drawn lines and a margin around them, if there are any.
+fn ink_bounds(page: &Page, palette: &Palette) -> Option<[f32; 4]> {
+    let mut bounds: Option<[f32; 4]> = None;
+    for line in page.layers.iter().flat_map(|l| &l.lines) {
+        let pen = match pens::Pen::new(line, palette) {
+            Some(pen) if pen.blend != pens::Blend::Erase => pen,
+            _ => continue,
+        };
+        for (point, segment) in line.points.iter().zip(pen.segments(line)) {
+            let reach = segment.width / 2. + INK_MARGIN;
+            let [x0, y0, x1, y1] = bounds.unwrap_or([point.x, point.y, point.x, point.y]);
+            bounds = Some([x0.min(point.x - reach), y0.min(point.y - reach), x1.max(point.x + reach), y1.max(point.y + reach)]);
+        }
+    }
+    bounds
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_page_ranges() {
+        let ranges: PageRanges = "1-3, 8,2,10-".parse().expect("valid ranges");
+        assert_eq!(ranges.indexes(11), [0, 1, 2, 7, 9, 10]);
+        assert!("0".parse::<PageRanges>().is_err());
+        assert!("2-x".parse::<PageRanges>().is_err());
+        assert!("5-3".parse::<PageRanges>().is_err());
+    }
+
+    #[test]
+    fn it_selects_layers_by_name_or_position() {
+        let layer = |name: Option<&str>| Layer { name: name.map(String::from), lines: Vec::new() };
+        let page = Page { layers: vec![layer(None), layer(Some("Sketch")), layer(Some("Final"))], text: None };
+        let names = |selection: &str| -> Vec<Option<String>> {
+            let selection: LayerSelection = selection.parse().expect("valid selection");
+            selection.apply(&page).layers.into_iter().map(|l| l.name).collect()
+        };
+        assert_eq!(names("Final, 1"), [Some("Layer 1".to_string()), Some("Final".to_string())]);
+        assert_eq!(names("2"), [Some("Sketch".to_string())]);
+        assert!(" ,".parse::<LayerSelection>().is_err());
+    }
+
+    #[test]
+    fn it_maps_cropped_and_turned_pages_to_the_output() {
+        let viewport = Viewport {
+            bounds: [100., 200., 500., 400.],
+            scale: 2.,
+            orientation: Orientation::Landscape,
+            device: DeviceProfile::default(),
+        };
+        assert_eq!(viewport.size(), (400., 800.));
+        let [a, b, c, d, e, f] = viewport.transform();
+        let map = |x: f32, y: f32| (a * x + c * y + e, b * x + d * y + f);
+        // the top left corner ends up in the top right one
+        assert_eq!(map(100., 200.), (400., 0.));
+        assert_eq!(map(500., 400.), (0., 800.));
+        assert_eq!(viewport.template_transform(), viewport.transform());
+    }
+
+    #[test]
+    fn it_extends_pages_down_to_their_ink() {
+        use crate::lines::{BrushType, Color, Layer, Line, Point};
+        let point = |x, y| Point { x, y, speed: 0., direction: 0., width: 2., pressure: 1. };
+        let line = Line { brush_type: BrushType::Marker, color: Color::Black, brush_base_size: 2., points: vec![point(300., 400.), point(500., 2500.)] };
+        let page = Page { layers: vec![Layer { name: None, lines: vec![line] }], text: None };
+        // half the width of the marker and the margin
+        let reach = 1. + INK_MARGIN;
+
+        let options = RenderOptions { page_heights: vec![Some(2000.)], ..RenderOptions::default() };
+        assert_eq!(Viewport::new(0, &page, &options).bounds, [0., 0., WIDTH, 2500. + reach]);
+        assert_eq!(Viewport::new(0, &Page::default(), &options).bounds, [0., 0., WIDTH, 2000.]);
+
+        let options = RenderOptions { crop: Crop::Content, ..RenderOptions::default() };
+        assert_eq!(Viewport::new(0, &page, &options).bounds, [300. - reach, 400. - reach, 500. + reach, 2500. + reach]);
+
+        let zoom = Zoom { center: (0., 936.), scale: 2. };
+        let options = RenderOptions { crop: Crop::Zoom, zoom: Some(zoom), ..RenderOptions::default() };
+        assert_eq!(Viewport::new(0, &page, &options).bounds, [351., 468., 1053., 1404.]);
+    }
+
+    #[test]
+    fn it_crops_horizontal_lines_to_their_width() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        use crate::lines::{BrushType, Color, Layer, Line, Point};
+        let point = |x| Point { x, y: 300., speed: 0., direction: 0., width: 2., pressure: 1. };
+        let line = Line { brush_type: BrushType::Highlighter, color: Color::Yellow, brush_base_size: 2., points: vec![point(100.), point(500.)] };
+        let page = Page { layers: vec![Layer { name: None, lines: vec![line] }], text: None };
+        let options = RenderOptions { crop: Crop::Content, ..RenderOptions::default() };
+
+        // highlighters are 30 pixels wide
+        let [_, top, _, bottom] = Viewport::new(0, &page, &options).bounds;
+        assert_eq!((top, bottom), (285. - INK_MARGIN, 315. + INK_MARGIN));
+        let mut output = Vec::new();
+        PngRenderer.render(&mut output, &[(0, &page)], &options)?;
+        PdfRenderer.render(&mut output, &[(0, &page)], &options)?;
+        Ok(())
+    }
+
+    #[test]
+    fn it_draws_templates_below_strokes() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let directory = tempfile::tempdir()?;
+        std::fs::write(
+            directory.path().join("P Lines small.svg"),
+            "<?xml version=\"1.0\"?>\n<svg xmlns=\"http://www.w3.org/2000/svg\"><line id=\"ruling\"/></svg>\n",
+        )?;
+        let template = Template::load(directory.path(), "P Lines small")?;
+        assert!(Template::load(directory.path(), "Blank")?.is_empty());
+
+        let mut output = Vec::new();
+        let options = RenderOptions { templates: vec![template], ..RenderOptions::default() };
+        SvgRenderer.render(&mut output, &[(0, &Page::default())], &options)?;
+        let output = String::from_utf8_lossy(&output);
+        assert!(!output.contains("<?xml"));
+        assert!(output.find("ruling") < output.find("</svg>"));
+        Ok(())
+    }
+}
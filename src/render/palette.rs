@@ -0,0 +1,151 @@
+//! RGB values of the colors stored in lines files.
+
+use crate::lines::Color;
+use serde::{Deserialize, Serialize};
+
+/// A color as `#rrggbb`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Rgb(pub [u8; 3]);
+
+impl Rgb {
+    /// Components between `0.0` and `1.0`, as used by PDF.
+    pub fn to_f32(self) -> [f32; 3] {
+        self.0.map(|c| c as f32 / 255.)
+    }
+}
+
+impl TryFrom<String> for Rgb {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let hex = value.strip_prefix('#').filter(|hex| hex.len() == 6 && hex.is_ascii());
+        let component = |i: usize| hex.and_then(|hex| u8::from_str_radix(&hex[i..i + 2], 16).ok());
+        match (component(0), component(2), component(4)) {
+            (Some(r), Some(g), Some(b)) => Ok(Self([r, g, b])),
+            _ => Err(format!("invalid color {}, expected #rrggbb", value)),
+        }
+    }
+}
+
+impl From<Rgb> for String {
+    fn from(rgb: Rgb) -> Self {
+        let [r, g, b] = rgb.0;
+        format!("#{:02x}{:02x}{:02x}", r, g, b)
+    }
+}
+
+/// The RGB value of each color, overridable in the `[palette]` section of the config,
+/// for example to print highlights in lighter colors.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Palette {
+    pub black: Rgb,
+    pub grey: Rgb,
+    pub white: Rgb,
+    pub yellow: Rgb,
+    pub green: Rgb,
+    pub pink: Rgb,
+    pub blue: Rgb,
+    pub red: Rgb,
+    pub grey_overlap: Rgb,
+    pub highlight: Rgb,
+    pub green_highlight: Rgb,
+    pub cyan: Rgb,
+    pub magenta: Rgb,
+    pub yellow_highlight: Rgb,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            black: Rgb([0, 0, 0]),
+            grey: Rgb([144, 144, 144]),
+            white: Rgb([255, 255, 255]),
+            yellow: Rgb([251, 247, 25]),
+            green: Rgb([0, 255, 0]),
+            pink: Rgb([255, 192, 203]),
+            blue: Rgb([78, 105, 201]),
+            red: Rgb([179, 62, 57]),
+            grey_overlap: Rgb([125, 125, 125]),
+            highlight: Rgb([255, 237, 117]),
+            green_highlight: Rgb([161, 216, 125]),
+            cyan: Rgb([139, 208, 229]),
+            magenta: Rgb([183, 130, 205]),
+            yellow_highlight: Rgb([247, 232, 81]),
+        }
+    }
+}
+
+impl Palette {
+    /// Colors we don't know are drawn black.
+    pub fn rgb(&self, color: Color) -> Rgb {
+        match color {
+            Color::Black | Color::Unknown(_) => self.black,
+            Color::Grey => self.grey,
+            Color::White => self.white,
+            Color::Yellow => self.yellow,
+            Color::Green => self.green,
+            Color::Pink => self.pink,
+            Color::Blue => self.blue,
+            Color::Red => self.red,
+            Color::GreyOverlap => self.grey_overlap,
+            Color::Highlight => self.highlight,
+            Color::GreenHighlight => self.green_highlight,
+            Color::Cyan => self.cyan,
+            Color::Magenta => self.magenta,
+            Color::YellowHighlight => self.yellow_highlight,
+        }
+    }
+
+    /// Returns the palette with each color turned to the grey of the same brightness, as monochrome screens show them.
+    pub fn greyscale(&self) -> Self {
+        let grey = |Rgb([r, g, b]): Rgb| {
+            let luma = (0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32).round() as u8;
+            Rgb([luma; 3])
+        };
+        Self {
+            black: grey(self.black),
+            grey: grey(self.grey),
+            white: grey(self.white),
+            yellow: grey(self.yellow),
+            green: grey(self.green),
+            pink: grey(self.pink),
+            blue: grey(self.blue),
+            red: grey(self.red),
+            grey_overlap: grey(self.grey_overlap),
+            highlight: grey(self.highlight),
+            green_highlight: grey(self.green_highlight),
+            cyan: grey(self.cyan),
+            magenta: grey(self.magenta),
+            yellow_highlight: grey(self.yellow_highlight),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Config {
+        palette: Palette,
+    }
+
+    #[test]
+    fn it_overrides_single_colors() {
+        let config: Config = toml::from_str("[palette]\nred = \"#FF0000\"\n").expect("valid palette");
+        assert_eq!(config.palette.rgb(Color::Red), Rgb([255, 0, 0]));
+        assert_eq!(config.palette.rgb(Color::Blue), Palette::default().blue);
+        assert_eq!(String::from(Rgb([255, 0, 16])), "#ff0010");
+        assert!(toml::from_str::<Config>("[palette]\nred = \"red\"\n").is_err());
+    }
+
+    #[test]
+    fn it_turns_colors_grey() {
+        let palette = Palette::default().greyscale();
+        assert_eq!(palette.rgb(Color::Black), Rgb([0, 0, 0]));
+        assert_eq!(palette.rgb(Color::Red), Rgb([96; 3]));
+        assert!(palette.rgb(Color::Yellow).0[0] > palette.rgb(Color::Blue).0[0]);
+    }
+}
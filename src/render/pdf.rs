@@ -0,0 +1,581 @@
+//! Renders pages to PDF, either as a new document or on top of the PDF they annotate.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::Arc;
+use snafu::ResultExt;
+use crate::lines::Page;
+use lopdf::content::{Content, Operation};
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, Stream};
+use super::{concat, layer_name, templates, Palette, Template, Renderer, RenderOptions, Result, PdfSnafu, WriteSnafu, Viewport, WIDTH, HEIGHT};
+use super::pens::{Blend, Outline, Pen};
+
+/// Renders all selected pages into a single PDF, one point per screen pixel at scale `1.0`.
+///
+/// Documents with more than one layer get an optional content group per layer name, to toggle them in viewers.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PdfRenderer;
+
+impl Renderer for PdfRenderer {
+    fn extension(&self) -> &str {
+        "pdf"
+    }
+
+    fn multi_page(&self) -> bool {
+        true
+    }
+
+    fn render(&self, mut output: &mut dyn Write, pages: &[(usize, &Page)], options: &RenderOptions) -> Result<()> {
+        let mut document = Document::with_version("1.5");
+        let pages_id = document.new_object_id();
+
+        let mut names: Vec<String> = Vec::new();
+        for (index, layer) in pages.iter().flat_map(|(_, page)| page.layers.iter().enumerate()) {
+            let name = layer_name(index, layer);
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        let groups: Vec<(String, ObjectId)> = match names.len() {
+            0 | 1 => Vec::new(),
+            _ => names
+                .into_iter()
+                .map(|name| {
+                    let id = document.add_object(dictionary! {
+                        "Type" => "OCG",
+                        "Name" => Object::string_literal(name.as_str()),
+                    });
+                    (name, id)
+                })
+                .collect(),
+        };
+
+        // images of templates shared by several pages are embedded once
+        let mut images = HashMap::new();
+        let mut kids = Vec::new();
+        for &(index, page) in pages {
+            let viewport = Viewport::new(index, page, options);
+            let (width, height) = viewport.size();
+            // PDF coordinates grow upwards
+            let flip = [1., 0., 0., -1., 0., height];
+            let transform = concat(flip, viewport.transform());
+            let template_transform = concat(flip, viewport.template_transform());
+
+            let mut operations = Vec::new();
+            let mut resources = Resources::default();
+            let template = options.templates.get(index);
+            if let Some(image) = template.and_then(|t| t.image.as_ref()) {
+                let image_id = *images.entry(Arc::as_ptr(image)).or_insert_with(|| {
+                    document.add_object(Stream::new(dictionary! {
+                        "Type" => "XObject",
+                        "Subtype" => "Image",
+                        "Width" => image.width as i64,
+                        "Height" => image.height as i64,
+                        "ColorSpace" => image.color_space,
+                        "BitsPerComponent" => 8,
+                    }, image.pixels.clone()))
+                });
+                resources.xobjects.set("Template", image_id);
+                operations.extend([
+                    Operation::new("q", vec![]),
+                    Operation::new("cm", template_transform.iter().map(|&v| v.into()).collect()),
+                    // images fill the unit square, with their first row at the top
+                    Operation::new("cm", vec![WIDTH.into(), 0.into(), 0.into(), (-HEIGHT).into(), 0.into(), HEIGHT.into()]),
+                    Operation::new("Do", vec!["Template".into()]),
+                    Operation::new("Q", vec![]),
+                ]);
+            }
+            if let Some(template) = template.filter(|t| t.image.is_none()) {
+                operations.append(&mut rules(template, template_transform));
+            }
+            operations.append(&mut strokes(&mut document, page, transform, &options.palette, &groups, &mut resources).context(PdfSnafu)?);
+            let mut dictionary = Dictionary::new();
+            for (category, entries) in resources.entries() {
+                dictionary.set(category, entries);
+            }
+            let content = Content { operations };
+            let content_id = document.add_object(Stream::new(dictionary! {}, content.encode().context(PdfSnafu)?));
+            let page_id = document.add_object(dictionary! {
+                "Type" => "Page",
+                "Parent" => pages_id,
+                "MediaBox" => vec![0.into(), 0.into(), width.into(), height.into()],
+                "Resources" => dictionary,
+                "Contents" => content_id,
+            });
+            kids.push(page_id.into());
+        }
+
+        document.objects.insert(pages_id, Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Count" => kids.len() as i64,
+            "Kids" => kids,
+        }));
+        let mut catalog = dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        };
+        if !groups.is_empty() {
+            let ids: Vec<Object> = groups.iter().map(|(_, id)| (*id).into()).collect();
+            catalog.set("OCProperties", dictionary! {
+                "OCGs" => ids.clone(),
+                "D" => dictionary! { "Order" => ids.clone(), "ON" => ids },
+            });
+        }
+        let catalog_id = document.add_object(catalog);
+        document.trailer.set("Root", catalog_id);
+        document.compress();
+        document.save_to(&mut output).context(WriteSnafu)?;
+        Ok(())
+    }
+}
+
+/// Renders all selected pages on top of the pages of the `original` PDF they were annotated on, into a single PDF.
+///
+/// Only the palette, device and orientation of the options apply, original pages keep their size and templates are left out.
+#[derive(Debug, Clone)]
+pub struct AnnotatedPdfRenderer {
+    pub original: Document,
+    /// Maps each page of the document to the index of the original page, `-1` marks blank pages inserted on the tablet.
+    /// Those get the size of the page before them.
+    pub redirection_page_map: Vec<isize>,
+}
+
+impl Renderer for AnnotatedPdfRenderer {
+    fn extension(&self) -> &str {
+        "pdf"
+    }
+
+    fn multi_page(&self) -> bool {
+        true
+    }
+
+    fn render(&self, mut output: &mut dyn Write, pages: &[(usize, &Page)], options: &RenderOptions) -> Result<()> {
+        let document = self.original.clone();
+        overlay(document, &mut output, pages, &self.redirection_page_map, options).context(PdfSnafu)
+    }
+}
+
+/// Draws `pages` on top of the pages of `document` they were annotated on, dropping all other pages.
+/// Documents without any pages are written unchanged.
+fn overlay(mut document: Document, output: &mut dyn Write, pages: &[(usize, &Page)], redirection_page_map: &[isize], options: &RenderOptions) -> lopdf::Result<()> {
+    let originals = document.get_pages();
+    let pages_id = document.catalog()?.get(b"Pages")?.as_reference()?;
+
+    if pages.is_empty() {
+        document.save_to(&mut &mut *output)?;
+        return Ok(());
+    }
+
+    let screen = Viewport {
+        bounds: options.device.bounds(),
+        scale: 1.,
+        orientation: options.orientation.unwrap_or_default(),
+        device: options.device,
+    };
+    let (width, height) = screen.size();
+    let mut kids = Vec::new();
+    let mut media_box = vec![0.into(), 0.into(), width.into(), height.into()];
+    for &(index, page) in pages {
+        let original_page = redirection_page_map
+            .get(index)
+            .copied()
+            .unwrap_or(index as isize);
+        let page_id = match u32::try_from(original_page).ok().and_then(|p| originals.get(&(p + 1))) {
+            Some(&page_id) => {
+                flatten_inherited(&mut document, page_id)?;
+                media_box = document.get_dictionary(page_id)?.get(b"MediaBox")?.as_array()?.clone();
+                isolate_contents(&mut document, page_id)?;
+                page_id
+            }
+            None => document.add_object(dictionary! {
+                "Type" => "Page",
+                "MediaBox" => media_box.clone(),
+                "Contents" => Vec::<Object>::new(),
+            }),
+        };
+
+        let mut resources = Resources::default();
+        let transform = fit_transform(&media_box, &screen)?;
+        let content = Content { operations: strokes(&mut document, page, transform, &options.palette, &[], &mut resources)? };
+        document.add_page_contents(page_id, content.encode()?)?;
+        for (category, entries) in resources.entries() {
+            add_resources(&mut document, page_id, category, entries)?;
+        }
+        document.get_dictionary_mut(page_id)?.set("Parent", pages_id);
+        kids.push(page_id.into());
+    }
+
+    let root = document.get_dictionary_mut(pages_id)?;
+    root.set("Count", kids.len() as i64);
+    root.set("Kids", kids);
+    document.prune_objects();
+    document.compress();
+    document.save_to(&mut &mut *output)?;
+    Ok(())
+}
+
+/// Width and height of each page in points, including sizes inherited from the page tree.
+pub(crate) fn page_sizes(document: &Document) -> lopdf::Result<Vec<(f32, f32)>> {
+    document
+        .get_pages()
+        .values()
+        .map(|&page_id| {
+            let mut node = Some(page_id);
+            while let Some(node_id) = node {
+                let dictionary = document.get_dictionary(node_id)?;
+                if let Ok(media_box) = dictionary.get(b"MediaBox") {
+                    let [x0, y0, x1, y1] = corners(media_box.as_array()?)?;
+                    return Ok((x1 - x0, y1 - y0));
+                }
+                node = dictionary.get(b"Parent").and_then(Object::as_reference).ok();
+            }
+            Err(lopdf::Error::DictKey)
+        })
+        .collect()
+}
+
+fn corners(media_box: &[Object]) -> lopdf::Result<[f32; 4]> {
+    match media_box {
+        [x0, y0, x1, y1] => Ok([x0.as_float()?, y0.as_float()?, x1.as_float()?, y1.as_float()?]),
+        _ => Err(lopdf::Error::Type),
+    }
+}
+
+/// The tablet fits PDF pages into its screen, turned as in `screen`, anchored at the top left corner.
+fn fit_transform(media_box: &[Object], screen: &Viewport) -> lopdf::Result<[f32; 6]> {
+    let [x0, y0, x1, y1] = corners(media_box)?;
+    let (width, height) = screen.size();
+    let scale = ((x1 - x0) / width).max((y1 - y0) / height);
+    Ok(concat([scale, 0., 0., -scale, x0, y1], screen.transform()))
+}
+
+/// Copies attributes a page inherits from its ancestors in the page tree onto the page itself,
+/// so that it can be re-parented.
+fn flatten_inherited(document: &mut Document, page_id: ObjectId) -> lopdf::Result<()> {
+    for key in [&b"MediaBox"[..], b"CropBox", b"Resources", b"Rotate"] {
+        if document.get_dictionary(page_id)?.has(key) {
+            continue;
+        }
+        let mut node = document.get_dictionary(page_id)?.get(b"Parent").and_then(Object::as_reference).ok();
+        while let Some(node_id) = node {
+            let dictionary = document.get_dictionary(node_id)?;
+            if let Ok(value) = dictionary.get(key) {
+                let value = value.clone();
+                document.get_dictionary_mut(page_id)?.set(key, value);
+                break;
+            }
+            node = dictionary.get(b"Parent").and_then(Object::as_reference).ok();
+        }
+    }
+    Ok(())
+}
+
+/// Wraps the existing content of a page in a saved graphics state, so that our strokes
+/// are drawn in an untransformed coordinate system.
+fn isolate_contents(document: &mut Document, page_id: ObjectId) -> lopdf::Result<()> {
+    let save = document.add_object(Stream::new(dictionary! {}, b"q\n".to_vec()));
+    let restore = document.add_object(Stream::new(dictionary! {}, b"Q\n".to_vec()));
+    let mut contents: Vec<Object> = vec![save.into()];
+    contents.extend(document.get_page_contents(page_id).into_iter().map(Object::from));
+    contents.push(restore.into());
+    document.get_dictionary_mut(page_id)?.set("Contents", contents);
+    Ok(())
+}
+
+/// Draws the rules of a built-in template.
+fn rules(template: &Template, transform: [f32; 6]) -> Vec<Operation> {
+    let mut operations = vec![
+        Operation::new("q", vec![]),
+        Operation::new("cm", transform.iter().map(|&v| v.into()).collect()),
+        Operation::new("G", vec![templates::RULE_GRAY.into()]),
+        Operation::new("J", vec![1.into()]),
+    ];
+    for rule in &template.rules {
+        operations.push(Operation::new("w", vec![rule.width.into()]));
+        operations.push(Operation::new("m", vec![rule.from.0.into(), rule.from.1.into()]));
+        operations.push(Operation::new("l", vec![rule.to.0.into(), rule.to.1.into()]));
+        operations.push(Operation::new("S", vec![]));
+    }
+    operations.push(Operation::new("Q", vec![]));
+    operations
+}
+
+/// Resources the content drawn on a page refers to by name.
+#[derive(Debug, Default)]
+struct Resources {
+    /// Graphics states setting opacity, blend mode or the mask of erasers.
+    states: Dictionary,
+    /// Optional content groups of layers.
+    properties: Dictionary,
+    /// Template images and ink masked by erasers.
+    xobjects: Dictionary,
+}
+
+impl Resources {
+    /// Each category of resources that is used, with its entries.
+    fn entries(self) -> impl Iterator<Item = (&'static str, Dictionary)> {
+        [("ExtGState", self.states), ("Properties", self.properties), ("XObject", self.xobjects)]
+            .into_iter()
+            .filter(|(_, entries)| !entries.is_empty())
+    }
+}
+
+/// Adds resources of a category such as `ExtGState` to an existing page, whose resources may be shared with other pages.
+fn add_resources(document: &mut Document, page_id: ObjectId, category: &str, entries: Dictionary) -> lopdf::Result<()> {
+    let resolve = |document: &Document, object: Option<&Object>| -> lopdf::Result<Dictionary> {
+        Ok(match object {
+            Some(Object::Reference(id)) => document.get_dictionary(*id)?.clone(),
+            Some(Object::Dictionary(dictionary)) => dictionary.clone(),
+            _ => Dictionary::new(),
+        })
+    };
+    let mut resources = resolve(document, document.get_dictionary(page_id)?.get(b"Resources").ok())?;
+    let mut existing = resolve(document, resources.get(category.as_bytes()).ok())?;
+    for (name, entry) in entries.iter() {
+        existing.set(name.clone(), entry.clone());
+    }
+    resources.set(category, existing);
+    document.get_dictionary_mut(page_id)?.set("Resources", resources);
+    Ok(())
+}
+
+/// Returns the name of a graphics state with the given opacity and blend mode, adding it to `states` if needed.
+fn graphics_state(states: &mut Dictionary, opacity: f32, blend: Blend) -> Object {
+    let opacity = (opacity * 100.).round() / 100.;
+    let (suffix, mode) = match blend {
+        Blend::Normal | Blend::Erase => ("", "Normal"),
+        Blend::Multiply => ("M", "Multiply"),
+    };
+    // prefixed, as overlays share resources with the original pdf
+    let name = format!("Unremarkable{}{}", (opacity * 100.) as u32, suffix);
+    if !states.has(name.as_bytes()) {
+        states.set(name.clone(), dictionary! {
+            "Type" => "ExtGState",
+            "CA" => opacity,
+            "ca" => opacity,
+            "BM" => mode,
+        });
+    }
+    Object::Name(name.into_bytes())
+}
+
+/// Draws the lines of all layers, each marked as the content of its optional content group in `groups`, if any.
+/// The resources used, such as groups, are added to `resources`.
+fn strokes(
+    document: &mut Document,
+    page: &Page,
+    transform: [f32; 6],
+    palette: &Palette,
+    groups: &[(String, ObjectId)],
+    resources: &mut Resources,
+) -> lopdf::Result<Vec<Operation>> {
+    let mut operations = vec![
+        Operation::new("q", vec![]),
+        Operation::new("cm", transform.iter().map(|&v| v.into()).collect()),
+    ];
+
+    for (index, layer) in page.layers.iter().enumerate() {
+        let name = layer_name(index, layer);
+        let group = groups.iter().position(|(group, _)| *group == name);
+        if let Some(group) = group {
+            let property = format!("Layer{}", group);
+            resources.properties.set(property.clone(), groups[group].1);
+            operations.push(Operation::new("BDC", vec![Object::Name(b"OC".to_vec()), Object::Name(property.into_bytes())]));
+        }
+        // erasers only remove ink drawn before them on the same layer
+        let mut ink = Vec::new();
+        let mut bounds: Option<[f32; 4]> = None;
+        let mut erasers = Vec::new();
+        for line in &layer.lines {
+            let pen = match Pen::new(line, palette) {
+                Some(pen) => pen,
+                None => continue,
+            };
+            let outlines = pen.outlines(line);
+            if pen.blend == Blend::Erase {
+                erasers.extend(outlines);
+                continue;
+            }
+            if let Some(bounds) = bounds.filter(|_| !erasers.is_empty()) {
+                ink = erase(document, ink, bounds, &std::mem::take(&mut erasers), resources)?;
+            }
+            if let Some([x0, y0, x1, y1]) = Outline::bounds(&outlines) {
+                let [a, b, c, d] = bounds.unwrap_or([x0, y0, x1, y1]);
+                bounds = Some([a.min(x0), b.min(y0), c.max(x1), d.max(y1)]);
+            }
+            ink.push(Operation::new("rg", pen.color.iter().map(|&c| c.into()).collect()));
+            for outline in &outlines {
+                if !outline.points.is_empty() {
+                    ink.push(Operation::new("gs", vec![graphics_state(&mut resources.states, outline.opacity, pen.blend)]));
+                    ink.append(&mut fill(outline));
+                }
+            }
+        }
+        if let Some(bounds) = bounds.filter(|_| !erasers.is_empty()) {
+            ink = erase(document, ink, bounds, &erasers, resources)?;
+        }
+        operations.append(&mut ink);
+        if group.is_some() {
+            operations.push(Operation::new("EMC", vec![]));
+        }
+    }
+
+    operations.push(Operation::new("Q", vec![]));
+    Ok(operations)
+}
+
+/// Fills an outline as a closed path.
+fn fill(outline: &Outline) -> Vec<Operation> {
+    let (first, rest) = match outline.points.split_first() {
+        Some(split) => split,
+        None => return Vec::new(),
+    };
+    let mut operations = vec![Operation::new("m", vec![first.0.into(), first.1.into()])];
+    for point in rest {
+        operations.push(Operation::new("l", vec![point.0.into(), point.1.into()]));
+    }
+    operations.push(Operation::new("h", vec![]));
+    operations.push(Operation::new("f", vec![]));
+    operations
+}
+
+/// Moves `ink` within `bounds` into a transparency group, drawn with a soft mask that is
+/// black where the outlines of `erasers` are, so that they remove the ink but nothing below it.
+fn erase(document: &mut Document, ink: Vec<Operation>, bounds: [f32; 4], erasers: &[Outline], resources: &mut Resources) -> lopdf::Result<Vec<Operation>> {
+    let [x0, y0, x1, y1] = bounds;
+    let bbox: Vec<Object> = bounds.iter().map(|&v| v.into()).collect();
+    let mut mask = vec![
+        Operation::new("g", vec![1.into()]),
+        Operation::new("re", vec![x0.into(), y0.into(), (x1 - x0).into(), (y1 - y0).into()]),
+        Operation::new("f", vec![]),
+        Operation::new("g", vec![0.into()]),
+    ];
+    mask.extend(erasers.iter().flat_map(fill));
+    let mask_id = document.add_object(Stream::new(dictionary! {
+        "Type" => "XObject",
+        "Subtype" => "Form",
+        "BBox" => bbox.clone(),
+        "Group" => dictionary! { "S" => "Transparency", "CS" => "DeviceGray" },
+    }, Content { operations: mask }.encode()?));
+    let ink_id = document.add_object(Stream::new(dictionary! {
+        "Type" => "XObject",
+        "Subtype" => "Form",
+        "BBox" => bbox,
+        "Group" => dictionary! { "S" => "Transparency" },
+        "Resources" => dictionary! {
+            "ExtGState" => resources.states.clone(),
+            "XObject" => resources.xobjects.clone(),
+        },
+    }, Content { operations: ink }.encode()?));
+
+    // prefixed, as overlays share resources with the original pdf
+    let count = resources.xobjects.len();
+    let (state, form) = (format!("UnremarkableErase{}", count), format!("UnremarkableInk{}", count));
+    resources.states.set(state.clone(), dictionary! {
+        "Type" => "ExtGState",
+        "SMask" => dictionary! {
+            "Type" => "Mask",
+            "S" => "Luminosity",
+            "G" => mask_id,
+            // outside of the mask, nothing is erased
+            "BC" => vec![1.into()],
+        },
+    });
+    resources.xobjects.set(form.clone(), ink_id);
+    Ok(vec![
+        Operation::new("q", vec![]),
+        Operation::new("gs", vec![Object::Name(state.into_bytes())]),
+        Operation::new("Do", vec![Object::Name(form.into_bytes())]),
+        Operation::new("Q", vec![]),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lines::{BrushType, Color, Layer, Line, Point};
+
+    #[test]
+    fn it_adds_an_optional_content_group_per_layer() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let point = |x| Point { x, y: 100., speed: 0., direction: 0., width: 4., pressure: 1. };
+        let line = Line { brush_type: BrushType::Fineliner, color: Color::Black, brush_base_size: 2., points: vec![point(100.), point(500.)] };
+        let layer = |name: &str| Layer { name: Some(name.to_string()), lines: vec![line.clone()] };
+        let page = Page { layers: vec![layer("Sketch"), layer("Final")], text: None };
+
+        let mut output = Vec::new();
+        PdfRenderer.render(&mut output, &[(0, &page), (1, &page)], &RenderOptions::default())?;
+        let document = Document::load_mem(&output)?;
+        let groups = document.catalog()?.get(b"OCProperties")?.as_dict()?.get(b"OCGs")?.as_array()?;
+        assert_eq!(groups.len(), 2);
+        let content = document.get_page_content(document.page_iter().next().ok_or("no pages")?)?;
+        assert!(String::from_utf8_lossy(&content).contains("/OC /Layer1 BDC"));
+        Ok(())
+    }
+
+    #[test]
+    fn it_erases_ink_but_keeps_the_original() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let mut original = Vec::new();
+        let options = RenderOptions { templates: Template::builtin("P Lines small").into_iter().collect(), ..RenderOptions::default() };
+        PdfRenderer.render(&mut original, &[(0, &Page::default())], &options)?;
+        let point = |x| Point { x, y: 100., speed: 0., direction: 0., width: 4., pressure: 1. };
+        let line = |brush_type| Line { brush_type, color: Color::Black, brush_base_size: 2., points: vec![point(100.), point(500.)] };
+        let lines = vec![line(BrushType::Fineliner), line(BrushType::Eraser), line(BrushType::Fineliner)];
+        let page = Page { layers: vec![Layer { name: None, lines }], text: None };
+
+        let renderer = AnnotatedPdfRenderer { original: Document::load_mem(&original)?, redirection_page_map: vec![0] };
+        let mut output = Vec::new();
+        renderer.render(&mut output, &[(0, &page)], &RenderOptions::default())?;
+        let document = Document::load_mem(&output)?;
+        let page_id = document.page_iter().next().ok_or("no pages")?;
+        let content = String::from_utf8_lossy(&document.get_page_content(page_id)?).into_owned();
+        // the rules of the original, then the erased line, then the line drawn after the eraser
+        assert!(content.contains(" l\nS"));
+        assert!(content.contains("/UnremarkableErase0 gs\n/UnremarkableInk0 Do"));
+        assert_eq!(content.matches(" rg").count(), 1);
+
+        let resources = document.get_dictionary(page_id)?.get(b"Resources")?.as_dict()?;
+        let state = resources.get(b"ExtGState")?.as_dict()?.get(b"UnremarkableErase0")?.as_dict()?;
+        let mask = document.get_object(state.get(b"SMask")?.as_dict()?.get(b"G")?.as_reference()?)?.as_stream()?;
+        assert!(String::from_utf8_lossy(&mask.decompressed_content()?).contains("0 g"));
+        let ink = document.get_object(resources.get(b"XObject")?.as_dict()?.get(b"UnremarkableInk0")?.as_reference()?)?.as_stream()?;
+        assert!(String::from_utf8_lossy(&ink.decompressed_content()?).contains(" rg"));
+        Ok(())
+    }
+
+    #[test]
+    fn it_keeps_only_the_selected_original_pages() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let mut original = Vec::new();
+        let blank = Page::default();
+        PdfRenderer.render(&mut original, &[(0, &blank), (1, &blank), (2, &blank)], &RenderOptions::default())?;
+        let renderer = AnnotatedPdfRenderer { original: Document::load_mem(&original)?, redirection_page_map: vec![0, -1, 1, 2] };
+
+        let mut output = Vec::new();
+        renderer.render(&mut output, &[(1, &blank), (3, &blank)], &RenderOptions::default())?;
+        let document = Document::load_mem(&output)?;
+        assert_eq!(document.get_pages().len(), 2);
+        assert_eq!(page_sizes(&document)?, [(WIDTH, HEIGHT), (WIDTH, HEIGHT)]);
+        Ok(())
+    }
+
+    #[test]
+    fn it_embeds_shared_template_images_once() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let directory = tempfile::tempdir()?;
+        let mut encoder = png::Encoder::new(std::fs::File::create(directory.path().join("P Lines small.png"))?, 2, 2);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.write_header()?.write_image_data(&[255, 0, 0, 255])?;
+        let template = Template::load(directory.path(), "P Lines small")?;
+
+        let page = Page::default();
+        let options = RenderOptions { templates: vec![template.clone(), template.clone(), template], ..RenderOptions::default() };
+        let mut output = Vec::new();
+        PdfRenderer.render(&mut output, &[(0, &page), (1, &page), (2, &page)], &options)?;
+        let document = Document::load_mem(&output)?;
+        let images = document.objects
+            .values()
+            .filter(|object| object.as_stream().is_ok_and(|s| s.dict.get(b"Subtype").is_ok_and(|t| t.as_name().is_ok_and(|name| name == b"Image"))))
+            .count();
+        assert_eq!(images, 1);
+        assert_eq!(document.get_pages().len(), 3);
+        Ok(())
+    }
+}
@@ -0,0 +1,221 @@
+//! How each brush type is drawn, approximating what the tablet shows.
+//!
+//! Widths and opacities follow the shapes of the formulas in [rmc](https://github.com/ricklupton/rmc),
+//! in screen pixels, with speed and pressure as stored in [`Point`].
+
+use crate::lines::{BrushType, Color, Line, Point};
+use super::palette::Palette;
+use super::tessellate::{outline, Cap};
+
+/// Lines are never drawn thinner than this, so fast strokes don't disappear.
+const MIN_WIDTH: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Blend {
+    Normal,
+    /// Darkens what is below, as the highlighter does.
+    Multiply,
+    /// Removes the ink drawn before it on the same layer, as erasers do, keeping templates and PDF pages below.
+    Erase,
+}
+
+/// Segments of a line with similar opacity, as a polygon to fill.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outline {
+    pub opacity: f32,
+    pub points: Vec<(f32, f32)>,
+}
+
+impl Outline {
+    /// Left, top, right and bottom edge of all outlines, a pixel wider for anti-aliasing, if there are any points.
+    pub fn bounds<'a>(outlines: impl IntoIterator<Item = &'a Outline>) -> Option<[f32; 4]> {
+        outlines
+            .into_iter()
+            .flat_map(|outline| &outline.points)
+            .fold(None, |bounds, &(x, y)| {
+                let [x0, y0, x1, y1] = bounds.unwrap_or([x, y, x, y]);
+                Some([x0.min(x), y0.min(y), x1.max(x), y1.max(y)])
+            })
+            .map(|[x0, y0, x1, y1]| [x0 - 1., y0 - 1., x1 + 1., y1 + 1.])
+    }
+}
+
+/// Width and opacity of the segment leading to a point.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Segment {
+    pub width: f32,
+    pub opacity: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pen {
+    pub brush_type: BrushType,
+    pub base_width: f32,
+    /// RGB, between `0.0` and `1.0`.
+    pub color: [f32; 3],
+    pub blend: Blend,
+    /// Square line caps, round ones otherwise.
+    pub square_cap: bool,
+}
+
+impl Pen {
+    /// Returns how a line is drawn, `None` for tools which leave no ink, such as selections.
+    ///
+    /// Erasers [erase](Blend::Erase) the ink below them, rather than painting over templates and PDF pages.
+    pub fn new(line: &Line, palette: &Palette) -> Option<Self> {
+        let ink = palette.rgb(line.color).to_f32();
+        let (color, blend, square_cap) = match line.brush_type {
+            BrushType::SelectionBrush | BrushType::EraseAll => return None,
+            BrushType::Eraser | BrushType::EraseArea => (ink, Blend::Erase, false),
+            // highlights made before colors were introduced are stored as black
+            BrushType::Highlighter => match line.color {
+                Color::Black | Color::Grey | Color::White => (palette.highlight.to_f32(), Blend::Multiply, true),
+                _ => (ink, Blend::Multiply, true),
+            },
+            BrushType::Shader => (ink, Blend::Multiply, false),
+            _ => (ink, Blend::Normal, false),
+        };
+        Some(Self { brush_type: line.brush_type, base_width: line.brush_base_size, color, blend, square_cap })
+    }
+
+    /// Returns the segment leading to each point of a line, the first one only matters for lines of a single point.
+    pub fn segments(&self, line: &Line) -> Vec<Segment> {
+        let mut last_width = self.base_width;
+        line.points
+            .iter()
+            .map(|point| {
+                let segment = self.segment(point, last_width);
+                last_width = segment.width;
+                segment
+            })
+            .collect()
+    }
+
+    /// Returns the outlines of a line, split where its opacity changes by a visible amount,
+    /// as a polygon can only be filled with a single one.
+    ///
+    /// The area eraser removes everything within its line, which is its only outline.
+    pub fn outlines(&self, line: &Line) -> Vec<Outline> {
+        if self.brush_type == BrushType::EraseArea {
+            let points = line.points.iter().map(|p| (p.x, p.y)).collect();
+            return vec![Outline { opacity: 1., points }];
+        }
+        let segments = self.segments(line);
+        let points: Vec<(f32, f32)> = line.points.iter().map(|p| (p.x, p.y)).collect();
+        let widths: Vec<f32> = segments.iter().map(|s| s.width).collect();
+        let opacity = |i: usize| (segments[i].opacity * 10.).round() / 10.;
+        let cap = if self.square_cap { Cap::Square } else { Cap::Round };
+        let last = match points.len() {
+            0 => return Vec::new(),
+            length => length - 1,
+        };
+
+        let mut outlines = Vec::new();
+        let mut start = 0;
+        loop {
+            // the segments leading to the points after `start` up to `end` share their opacity
+            let run_opacity = opacity((start + 1).min(last));
+            let mut end = (start + 1).min(last);
+            while end < last && opacity(end + 1) == run_opacity {
+                end += 1;
+            }
+            let (start_cap, end_cap) = (if start == 0 { cap } else { Cap::Butt }, if end == last { cap } else { Cap::Butt });
+            outlines.push(Outline {
+                opacity: run_opacity,
+                points: outline(&points[start..=end], &widths[start..=end], start_cap, end_cap),
+            });
+            if end == last {
+                return outlines;
+            }
+            start = end;
+        }
+    }
+
+    fn segment(&self, point: &Point, last_width: f32) -> Segment {
+        let Point { speed, direction, width, pressure, .. } = *point;
+        let speed = speed / 50.;
+        let (width, opacity) = match self.brush_type {
+            BrushType::Ballpoint => (
+                0.5 + pressure + width - 0.5 * speed,
+                (1.2 * pressure + 0.5 - 0.1 * speed).clamp(0.3, 1.),
+            ),
+            BrushType::Fineliner => (self.base_width.powf(2.1) * 1.3, 1.),
+            BrushType::Marker => (0.9 * width + 0.1 * last_width, 1.),
+            BrushType::Pencil => (
+                0.7 * ((0.8 * self.base_width + 0.5 * pressure) * width - 0.6 * speed),
+                (pressure - 0.1 * speed).clamp(0.1, 1.),
+            ),
+            BrushType::MechanicalPencil => (self.base_width.powi(2), 0.7),
+            BrushType::Paintbrush => (
+                0.7 * ((1. + 1.4 * pressure) * width - speed),
+                ((pressure.powf(1.5) - 0.2 * speed) * 1.5).clamp(0.1, 1.),
+            ),
+            // a nib held at 45 degrees, thin when moving along it
+            BrushType::Calligraphy => (
+                0.9 * (1. + pressure) * width * (0.3 + 0.7 * (direction - std::f32::consts::FRAC_PI_4).sin().abs()) + 0.1 * last_width,
+                1.,
+            ),
+            BrushType::Highlighter => (30., 0.5),
+            BrushType::Shader => (width.max(self.base_width * 10.), 0.15),
+            BrushType::Eraser => (self.base_width * 10., 1.),
+            _ => (width.max(self.base_width), 1.),
+        };
+        Segment { width: width.max(MIN_WIDTH), opacity }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(brush_type: BrushType) -> Line {
+        let point = |pressure| Point { x: 0., y: 0., speed: 10., direction: 0., width: 2., pressure };
+        Line { brush_type, color: Color::Black, brush_base_size: 2., points: vec![point(0.2), point(0.9)] }
+    }
+
+    #[test]
+    fn it_varies_widths_by_brush() {
+        let ballpoint = line(BrushType::Ballpoint);
+        let palette = Palette::default();
+        let segments = Pen::new(&ballpoint, &palette).expect("ballpoint draws").segments(&ballpoint);
+        assert!(segments[1].width > segments[0].width);
+        assert_ne!(segments[1].opacity, segments[0].opacity);
+
+        let fineliner = line(BrushType::Fineliner);
+        let segments = Pen::new(&fineliner, &palette).expect("fineliner draws").segments(&fineliner);
+        assert_eq!(segments[0], segments[1]);
+
+        let highlighter = Pen::new(&line(BrushType::Highlighter), &palette).expect("highlighter draws");
+        assert_eq!(highlighter.blend, Blend::Multiply);
+        assert_eq!(highlighter.color, palette.highlight.to_f32());
+        assert!(Pen::new(&line(BrushType::SelectionBrush), &palette).is_none());
+        assert_eq!(Pen::new(&line(BrushType::Eraser), &palette).expect("eraser erases").blend, Blend::Erase);
+    }
+
+    #[test]
+    fn it_uses_the_color_of_lines() {
+        let palette = Palette::default();
+        let red = Line { color: Color::Red, ..line(BrushType::Fineliner) };
+        assert_eq!(Pen::new(&red, &palette).expect("fineliner draws").color, palette.red.to_f32());
+    }
+
+    #[test]
+    fn it_splits_outlines_by_opacity() {
+        let palette = Palette::default();
+        let point = |x, pressure| Point { x, y: 0., speed: 0., direction: 0., width: 2., pressure };
+        let pencil = Line {
+            points: vec![point(0., 0.9), point(10., 0.9), point(20., 0.9), point(30., 0.2)],
+            ..line(BrushType::Pencil)
+        };
+        let outlines = Pen::new(&pencil, &palette).expect("pencil draws").outlines(&pencil);
+        assert_eq!(outlines.len(), 2);
+        assert!(outlines[0].opacity > outlines[1].opacity);
+
+        let dot = Line { points: vec![point(0., 0.5)], ..line(BrushType::Fineliner) };
+        assert_eq!(Pen::new(&dot, &palette).expect("fineliner draws").outlines(&dot).len(), 1);
+
+        let area = Line { points: vec![point(0., 0.5), point(10., 0.5), point(10., 0.5)], ..line(BrushType::EraseArea) };
+        let outlines = Pen::new(&area, &palette).expect("area eraser erases").outlines(&area);
+        assert_eq!(outlines, [Outline { opacity: 1., points: vec![(0., 0.), (10., 0.), (10., 0.)] }]);
+    }
+}
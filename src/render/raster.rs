@@ -0,0 +1,182 @@
+//! Renders pages to PNG, in pure Rust, for thumbnails and images to paste elsewhere.
+
+use std::io::Write;
+use snafu::ResultExt;
+use tiny_skia::{BlendMode, FillRule, LineCap, Paint, PathBuilder, Pixmap, PixmapPaint, Stroke, Transform};
+use crate::lines::Page;
+use super::{Template, Renderer, RenderOptions, Result, Error, WriteSnafu, Viewport, WIDTH, HEIGHT, templates::RULE_GRAY};
+use super::pens::{Blend, Pen};
+
+/// Renders a single page on top of its template to PNG.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PngRenderer;
+
+impl Renderer for PngRenderer {
+    fn extension(&self) -> &str {
+        "png"
+    }
+
+    fn render(&self, output: &mut dyn Write, pages: &[(usize, &Page)], options: &RenderOptions) -> Result<()> {
+        for &(index, page) in pages {
+            let png = render_png(index, page, options)?;
+            output.write_all(&png).context(WriteSnafu)?;
+        }
+        Ok(())
+    }
+}
+
+fn render_png(index: usize, page: &Page, options: &RenderOptions) -> Result<Vec<u8>> {
+    let viewport = Viewport::new(index, page, options);
+    let (width, height) = viewport.size();
+    let (width, height) = (width.round() as u32, height.round() as u32);
+    let mut pixmap = Pixmap::new(width, height).ok_or(Error::ImageSize { width, height })?;
+    pixmap.fill(tiny_skia::Color::WHITE);
+    let [a, b, c, d, e, f] = viewport.transform();
+    let transform = Transform::from_row(a, b, c, d, e, f);
+
+    if let Some(template) = options.templates.get(index) {
+        let [a, b, c, d, e, f] = viewport.template_transform();
+        draw_template(&mut pixmap, template, Transform::from_row(a, b, c, d, e, f));
+    }
+
+    // each layer is drawn on its own, so that its erasers only remove its ink
+    let mut ink = Pixmap::new(width, height).ok_or(Error::ImageSize { width, height })?;
+    for layer in &page.layers {
+        ink.fill(tiny_skia::Color::TRANSPARENT);
+        for line in &layer.lines {
+            let pen = match Pen::new(line, &options.palette) {
+                Some(pen) => pen,
+                None => continue,
+            };
+            for outline in pen.outlines(line) {
+                let mut path = PathBuilder::new();
+                let mut points = outline.points.iter();
+                match points.next() {
+                    Some(&(x, y)) => path.move_to(x, y),
+                    None => continue,
+                }
+                for &(x, y) in points {
+                    path.line_to(x, y);
+                }
+                path.close();
+                let path = match path.finish() {
+                    Some(path) => path,
+                    None => continue,
+                };
+                let [r, g, b] = pen.color;
+                let mut paint = Paint::default();
+                paint.set_color_rgba8((r * 255.) as u8, (g * 255.) as u8, (b * 255.) as u8, (outline.opacity * 255.) as u8);
+                paint.anti_alias = true;
+                paint.blend_mode = match pen.blend {
+                    Blend::Normal => BlendMode::SourceOver,
+                    Blend::Multiply => BlendMode::Multiply,
+                    Blend::Erase => BlendMode::DestinationOut,
+                };
+                ink.fill_path(&path, &paint, FillRule::Winding, transform, None);
+            }
+        }
+        pixmap.draw_pixmap(0, 0, ink.as_ref(), &PixmapPaint::default(), Transform::identity(), None);
+    }
+
+    pixmap.encode_png().map_err(|e| Error::EncodePng { message: e.to_string() })
+}
+
+fn draw_template(pixmap: &mut Pixmap, template: &Template, transform: Transform) {
+    if let Some(image) = &template.image {
+        let channels = image.pixels.len() / (image.width * image.height).max(1) as usize;
+        let rgba: Vec<u8> = image.pixels
+            .chunks_exact(channels.max(1))
+            .flat_map(|pixel| match pixel {
+                [gray] => [*gray, *gray, *gray, 255],
+                [r, g, b, ..] => [*r, *g, *b, 255],
+                _ => [255; 4],
+            })
+            .collect();
+        let size = tiny_skia::IntSize::from_wh(image.width, image.height);
+        if let Some(background) = size.and_then(|size| Pixmap::from_vec(rgba, size)) {
+            let fit = transform.pre_scale(WIDTH / image.width as f32, HEIGHT / image.height as f32);
+            pixmap.draw_pixmap(0, 0, background.as_ref(), &PixmapPaint::default(), fit, None);
+        }
+        return;
+    }
+
+    let gray = (RULE_GRAY * 255.) as u8;
+    let mut paint = Paint::default();
+    paint.set_color_rgba8(gray, gray, gray, 255);
+    paint.anti_alias = true;
+    for rule in &template.rules {
+        let path = if rule.from == rule.to {
+            PathBuilder::from_circle(rule.from.0, rule.from.1, rule.width / 2.)
+        } else {
+            let mut path = PathBuilder::new();
+            path.move_to(rule.from.0, rule.from.1);
+            path.line_to(rule.to.0, rule.to.1);
+            path.finish()
+        };
+        let path = match path {
+            Some(path) => path,
+            None => continue,
+        };
+        if rule.from == rule.to {
+            pixmap.fill_path(&path, &paint, FillRule::Winding, transform, None);
+        } else {
+            let stroke = Stroke { width: rule.width, line_cap: LineCap::Round, ..Stroke::default() };
+            pixmap.stroke_path(&path, &paint, &stroke, transform, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lines::{BrushType, Color, Layer, Line, Point};
+
+    #[test]
+    fn it_renders_pngs_at_the_given_resolution() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let point = |x| Point { x, y: 100., speed: 0., direction: 0., width: 4., pressure: 1. };
+        let line = Line { brush_type: BrushType::Fineliner, color: Color::Black, brush_base_size: 2., points: vec![point(100.), point(500.)] };
+        let page = Page { layers: vec![Layer { name: None, lines: vec![line] }], text: None };
+        let mut output = Vec::new();
+        let options = RenderOptions {
+            templates: Template::builtin("P Grid small").into_iter().collect(),
+            scale: 0.5,
+            orientation: Some(crate::render::Orientation::Landscape),
+            ..RenderOptions::default()
+        };
+        PngRenderer.render(&mut output, &[(0, &page)], &options)?;
+
+        let decoded = Pixmap::decode_png(&output)?;
+        assert_eq!((decoded.width(), decoded.height()), (936, 702));
+        // turned clockwise, the line runs down near the right edge
+        let ink = decoded.pixel(936 - 50, 150).expect("pixel on the line");
+        assert!(ink.red() < 50);
+        Ok(())
+    }
+
+    #[test]
+    fn it_erases_ink_but_keeps_templates() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let point = |x, y| Point { x, y, speed: 0., direction: 0., width: 4., pressure: 1. };
+        let line = |brush_type, points| Line { brush_type, color: Color::Black, brush_base_size: 2., points };
+        let ink = line(BrushType::Fineliner, vec![point(100., 100.), point(500., 100.)]);
+        let area = vec![point(50., 50.), point(550., 50.), point(550., 150.), point(50., 150.)];
+        let layer = |lines| Layer { name: None, lines };
+        let options = RenderOptions { templates: Template::builtin("P Grid small").into_iter().collect(), ..RenderOptions::default() };
+        let render = |page: &Page| -> Result<Vec<u8>> {
+            let mut output = Vec::new();
+            PngRenderer.render(&mut output, &[(0, page)], &options)?;
+            Ok(output)
+        };
+
+        let blank = render(&Page::default())?;
+        for eraser in [line(BrushType::Eraser, ink.points.clone()), line(BrushType::EraseArea, area)] {
+            let erased = Page { layers: vec![layer(vec![ink.clone(), eraser.clone()])], text: None };
+            assert!(render(&erased)? == blank, "ink below {:?} is left", eraser.brush_type);
+            // erasers only remove ink drawn before them on the same layer
+            let redrawn = Page { layers: vec![layer(vec![ink.clone(), eraser.clone(), ink.clone()])], text: None };
+            assert!(render(&redrawn)? != blank);
+            let below = Page { layers: vec![layer(vec![ink.clone()]), layer(vec![eraser])], text: None };
+            assert!(render(&below)? != blank);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,178 @@
+//! Renders pages to SVG, one file per page.
+
+use std::io::{self, Write};
+use snafu::ResultExt;
+use crate::lines::Page;
+use super::{escape, layer_name, templates, Renderer, RenderOptions, Result, WriteSnafu, Viewport, WIDTH, HEIGHT};
+use super::pens::{Blend, Outline, Pen};
+
+/// Renders a single page to SVG, with one group per layer that Inkscape shows as a layer.
+///
+/// Honours [`RenderOptions::debug_dump`] with a comment describing each line.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SvgRenderer;
+
+impl Renderer for SvgRenderer {
+    fn extension(&self) -> &str {
+        "svg"
+    }
+
+    fn render(&self, output: &mut dyn Write, pages: &[(usize, &Page)], options: &RenderOptions) -> Result<()> {
+        for &(index, page) in pages {
+            write_page(output, index, page, options).context(WriteSnafu)?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats a color for SVG.
+fn svg_color(color: [f32; 3]) -> String {
+    let [r, g, b] = color.map(|c| (c * 255.).round() as u8);
+    format!("rgb({},{},{})", r, g, b)
+}
+
+fn matrix([a, b, c, d, e, f]: [f32; 6]) -> String {
+    format!("matrix({} {} {} {} {} {})", a, b, c, d, e, f)
+}
+
+fn write_page(output: &mut dyn Write, index: usize, page: &Page, options: &RenderOptions) -> io::Result<()> {
+    let viewport = Viewport::new(index, page, options);
+    let template = options.templates.get(index);
+    let (width, height) = viewport.size();
+    writeln!(
+        output,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#,
+        width, height
+    )?;
+    if let Some(template) = template.filter(|t| !t.is_empty()) {
+        writeln!(output, "<g transform=\"{}\">", matrix(viewport.template_transform()))?;
+        if let Some(svg) = &template.svg {
+            // nested as is, without its xml declaration, in a viewport the size of the screen
+            let start = svg.find("<svg").unwrap_or(0);
+            writeln!(output, r#"<svg width="{}" height="{}">"#, WIDTH, HEIGHT)?;
+            writeln!(output, "{}", svg[start..].trim_end())?;
+            writeln!(output, "</svg>")?;
+        } else if let Some(png) = &template.png {
+            writeln!(
+                output,
+                r#"<image x="0" y="0" width="{}" height="{}" href="data:image/png;base64,{}"/>"#,
+                WIDTH, HEIGHT, base64::encode(png)
+            )?;
+        } else if !template.rules.is_empty() {
+            let gray = (templates::RULE_GRAY * 255.) as u8;
+            writeln!(output, r#"<g stroke="rgb({0},{0},{0})" stroke-linecap="round">"#, gray)?;
+            for rule in &template.rules {
+                writeln!(
+                    output,
+                    r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke-width="{}"/>"#,
+                    rule.from.0, rule.from.1, rule.to.0, rule.to.1, rule.width
+                )?;
+            }
+            writeln!(output, "</g>")?;
+        }
+        writeln!(output, "</g>")?;
+    }
+    writeln!(output, "<g transform=\"{}\">", matrix(viewport.transform()))?;
+    let mut masks = 0;
+    for (index, layer) in page.layers.iter().enumerate() {
+        writeln!(output, r#"<g inkscape:groupmode="layer" inkscape:label="{}">"#, escape(&layer_name(index, layer)))?;
+        // erasers only remove ink drawn before them on the same layer
+        let mut ink = Vec::new();
+        let mut bounds: Option<[f32; 4]> = None;
+        let mut erasers = Vec::new();
+        for line in &layer.lines {
+            let pen = match Pen::new(line, &options.palette) {
+                Some(pen) => pen,
+                None => continue,
+            };
+            let outlines = pen.outlines(line);
+            if pen.blend == Blend::Erase {
+                erasers.extend(outlines);
+                continue;
+            }
+            if let Some(bounds) = bounds.filter(|_| !erasers.is_empty()) {
+                ink = erase(ink, bounds, &std::mem::take(&mut erasers), &mut masks)?;
+            }
+            if let Some([x0, y0, x1, y1]) = Outline::bounds(&outlines) {
+                let [a, b, c, d] = bounds.unwrap_or([x0, y0, x1, y1]);
+                bounds = Some([a.min(x0), b.min(y0), c.max(x1), d.max(y1)]);
+            }
+            if options.debug_dump {
+                writeln!(ink, "<!-- {:?} {:?} {} points -->", line.brush_type, line.color, line.points.len())?;
+            }
+            let blend = match pen.blend {
+                Blend::Normal | Blend::Erase => "",
+                Blend::Multiply => r#" style="mix-blend-mode:multiply""#,
+            };
+            for outline in &outlines {
+                write_path(&mut ink, outline, &format!(r#"fill="{}" fill-opacity="{}"{}"#, svg_color(pen.color), outline.opacity, blend))?;
+            }
+        }
+        if let Some(bounds) = bounds.filter(|_| !erasers.is_empty()) {
+            ink = erase(ink, bounds, &erasers, &mut masks)?;
+        }
+        output.write_all(&ink)?;
+        writeln!(output, "</g>")?;
+    }
+    writeln!(output, "</g>")?;
+    writeln!(output, "</svg>")
+}
+
+/// Writes an outline as a closed path with the given attributes.
+fn write_path(output: &mut dyn Write, outline: &Outline, attributes: &str) -> io::Result<()> {
+    if outline.points.is_empty() {
+        return Ok(());
+    }
+    write!(output, r#"<path {} d="M"#, attributes)?;
+    for point in &outline.points {
+        write!(output, " {},{}", point.0, point.1)?;
+    }
+    writeln!(output, r#" Z"/>"#)
+}
+
+/// Wraps `ink` within `bounds` in a group with a mask that hides it where the outlines of `erasers` are.
+/// Masks are numbered by `masks`, so that their ids are unique within the page.
+fn erase(ink: Vec<u8>, bounds: [f32; 4], erasers: &[Outline], masks: &mut usize) -> io::Result<Vec<u8>> {
+    let [x0, y0, x1, y1] = bounds;
+    let id = format!("erase-{}", masks);
+    *masks += 1;
+    let mut output = Vec::new();
+    writeln!(
+        output,
+        r#"<mask id="{0}" maskUnits="userSpaceOnUse" x="{1}" y="{2}" width="{3}" height="{4}"><rect x="{1}" y="{2}" width="{3}" height="{4}" fill="white"/>"#,
+        id, x0, y0, x1 - x0, y1 - y0
+    )?;
+    for outline in erasers {
+        write_path(&mut output, outline, r#"fill="black""#)?;
+    }
+    writeln!(output, "</mask>")?;
+    writeln!(output, r#"<g mask="url(#{})">"#, id)?;
+    output.extend(ink);
+    writeln!(output, "</g>")?;
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lines::{BrushType, Color, Layer, Line, Point};
+
+    #[test]
+    fn it_masks_erased_ink() -> Result<()> {
+        let point = |x| Point { x, y: 100., speed: 0., direction: 0., width: 4., pressure: 1. };
+        let line = |brush_type| Line { brush_type, color: Color::Black, brush_base_size: 2., points: vec![point(100.), point(500.)] };
+        let lines = vec![line(BrushType::Fineliner), line(BrushType::Eraser), line(BrushType::Fineliner)];
+        let page = Page { layers: vec![Layer { name: None, lines }], text: None };
+        let mut output = Vec::new();
+        SvgRenderer.render(&mut output, &[(0, &page)], &RenderOptions::default())?;
+
+        let svg = String::from_utf8_lossy(&output);
+        let (masked, after) = svg.split_once(r#"<g mask="url(#erase-0)">"#).expect("erased ink is masked");
+        assert!(masked.contains(r#"<mask id="erase-0""#) && masked.contains(r#"fill="black""#));
+        // the line drawn after the eraser is left as is
+        let (erased, redrawn) = after.split_once("</g>").expect("mask group is closed");
+        assert_eq!(erased.matches("<path").count(), 1);
+        assert_eq!(redrawn.matches("<path").count(), 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,207 @@
+//! Page templates, either loaded from the device's files or generated from simple geometry.
+
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use super::{WIDTH, HEIGHT};
+
+/// Gray level of template lines, from black at `0` to white at `1`.
+pub const RULE_GRAY: f32 = 0.7;
+const SMALL: f32 = 52.;
+const MEDIUM: f32 = 70.;
+const LARGE: f32 = 88.;
+/// Space left for the page header by lined templates.
+const TOP: f32 = 208.;
+const MARGIN: f32 = 120.;
+const RULE_WIDTH: f32 = 1.5;
+
+/// A page template, as shipped on the device in `/usr/share/remarkable/templates`.
+///
+/// Most templates exist as both SVG and PNG, SVG output uses the former and PDF output the latter.
+#[derive(Debug, Clone, Default)]
+pub struct Template {
+    pub svg: Option<String>,
+    pub png: Option<Vec<u8>>,
+    /// Lines drawn if there is neither SVG nor PNG, as generated for built-in templates.
+    pub rules: Vec<Rule>,
+    /// Shared between clones, so that PDF output embeds each image once.
+    pub(super) image: Option<Arc<Image>>,
+}
+
+/// Decoded pixels of a PNG template, without alpha.
+#[derive(Debug, Clone)]
+pub(super) struct Image {
+    pub(super) width: u32,
+    pub(super) height: u32,
+    pub(super) color_space: &'static str,
+    pub(super) pixels: Vec<u8>,
+}
+
+/// A straight line of a built-in template in page coordinates, dots are lines of zero length.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rule {
+    pub from: (f32, f32),
+    pub to: (f32, f32),
+    pub width: f32,
+}
+
+impl Template {
+    /// Loads `{name}.svg` and `{name}.png` from a copy of the device's templates directory,
+    /// either of them may be missing.
+    pub fn load(directory: &Path, name: &str) -> io::Result<Self> {
+        let read = |extension: &str| match std::fs::read(directory.join(format!("{}.{}", name, extension))) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            result => result.map(Some),
+        };
+        let svg = read("svg")?.map(|svg| String::from_utf8_lossy(&svg).into_owned());
+        let png = read("png")?;
+        let image = png.as_deref().map(decode_png).transpose()?.map(Arc::new);
+        Ok(Self { svg, png, image, ..Default::default() })
+    }
+
+    /// Generates one of the common templates by its name in `.pagedata`, such as `P Lines small` or `P Dots S`.
+    ///
+    /// Spacings approximate the device's templates. Returns `None` for `Blank` and templates we don't know.
+    pub fn builtin(name: &str) -> Option<Self> {
+        let name = name.to_lowercase();
+        let words: Vec<&str> = name.split_whitespace().collect();
+        let spacing = if words.iter().any(|w| matches!(*w, "small" | "s")) {
+            SMALL
+        } else if words.iter().any(|w| matches!(*w, "large" | "l")) {
+            LARGE
+        } else {
+            MEDIUM
+        };
+        let has = |word: &str| words.iter().any(|w| w.starts_with(word));
+
+        let mut rules = if has("cornell") {
+            cornell()
+        } else if has("checklist") {
+            checklist()
+        } else if has("grid") {
+            grid(spacing)
+        } else if has("dot") {
+            dots(spacing)
+        } else if has("line") {
+            lines(spacing)
+        } else {
+            return None;
+        };
+        if has("margin") {
+            rules.push(Rule { from: (MARGIN, 0.), to: (MARGIN, HEIGHT), width: 2. });
+        }
+        Some(Self { rules, ..Default::default() })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.svg.is_none() && self.png.is_none() && self.rules.is_empty()
+    }
+}
+
+/// Decodes a PNG to 8 bit gray or RGB pixels, blending transparent pixels with white paper.
+fn decode_png(data: &[u8]) -> io::Result<Image> {
+    let invalid = |e| io::Error::new(io::ErrorKind::InvalidData, e);
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().map_err(invalid)?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(invalid)?;
+    buffer.truncate(info.buffer_size());
+
+    let (channels, alpha) = match info.color_type {
+        png::ColorType::Grayscale => (1, false),
+        png::ColorType::GrayscaleAlpha => (1, true),
+        png::ColorType::Rgb => (3, false),
+        png::ColorType::Rgba => (3, true),
+        // expanded to RGB by the decoder
+        png::ColorType::Indexed => return Err(io::Error::new(io::ErrorKind::InvalidData, "unexpected palette")),
+    };
+    let pixels = if alpha {
+        buffer
+            .chunks_exact(channels + 1)
+            .flat_map(|pixel| {
+                let alpha = pixel[channels] as u32;
+                pixel[..channels].iter().map(move |&v| ((v as u32 * alpha + 255 * (255 - alpha)) / 255) as u8)
+            })
+            .collect()
+    } else {
+        buffer
+    };
+    let color_space = if channels == 1 { "DeviceGray" } else { "DeviceRGB" };
+    Ok(Image { width: info.width, height: info.height, color_space, pixels })
+}
+
+fn horizontal(y: f32) -> Rule {
+    Rule { from: (0., y), to: (WIDTH, y), width: RULE_WIDTH }
+}
+
+fn vertical(x: f32) -> Rule {
+    Rule { from: (x, 0.), to: (x, HEIGHT), width: RULE_WIDTH }
+}
+
+/// Positions from `start` up to the end of the page, `spacing` apart.
+fn steps(start: f32, end: f32, spacing: f32) -> impl Iterator<Item = f32> {
+    (0..).map(move |i| start + i as f32 * spacing).take_while(move |v| *v < end)
+}
+
+fn lines(spacing: f32) -> Vec<Rule> {
+    steps(TOP, HEIGHT, spacing).map(horizontal).collect()
+}
+
+fn grid(spacing: f32) -> Vec<Rule> {
+    steps(spacing, HEIGHT, spacing).map(horizontal)
+        .chain(steps(spacing, WIDTH, spacing).map(vertical))
+        .collect()
+}
+
+fn dots(spacing: f32) -> Vec<Rule> {
+    steps(spacing, HEIGHT, spacing)
+        .flat_map(|y| steps(spacing, WIDTH, spacing).map(move |x| Rule { from: (x, y), to: (x, y), width: 5. }))
+        .collect()
+}
+
+/// Lines with a column for cues on the left and space for a summary at the bottom.
+fn cornell() -> Vec<Rule> {
+    let summary = HEIGHT - 5. * MEDIUM;
+    let mut rules: Vec<Rule> = steps(TOP, summary, MEDIUM).map(horizontal).collect();
+    rules.push(Rule { from: (WIDTH * 0.3, TOP), to: (WIDTH * 0.3, summary), width: 3. });
+    rules.push(Rule { width: 3., ..horizontal(summary) });
+    rules
+}
+
+/// Lines with a box to tick at the start of each.
+fn checklist() -> Vec<Rule> {
+    let size = MEDIUM * 0.45;
+    steps(TOP, HEIGHT, MEDIUM)
+        .flat_map(|y| {
+            let (x0, y0, x1, y1) = (MARGIN / 2., y - size - 12., MARGIN / 2. + size, y - 12.);
+            [
+                horizontal(y),
+                Rule { from: (x0, y0), to: (x1, y0), width: 2. },
+                Rule { from: (x1, y0), to: (x1, y1), width: 2. },
+                Rule { from: (x1, y1), to: (x0, y1), width: 2. },
+                Rule { from: (x0, y1), to: (x0, y0), width: 2. },
+            ]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_generates_common_templates() {
+        assert!(Template::builtin("Blank").is_none());
+        let small = Template::builtin("P Lines small").expect("lines template");
+        let large = Template::builtin("P Lines large").expect("lines template");
+        assert!(small.rules.len() > large.rules.len());
+        assert!(small.rules.iter().all(|r| r.from.1 == r.to.1));
+
+        let grid = Template::builtin("P Grid margin med").expect("grid template");
+        assert!(grid.rules.iter().any(|r| r.from.0 == MARGIN && r.to.0 == MARGIN));
+        assert!(Template::builtin("P Dots S").expect("dots template").rules.iter().all(|r| r.from == r.to));
+        assert!(!Template::builtin("P Cornell").expect("cornell template").is_empty());
+        assert!(!Template::builtin("P Checklist").expect("checklist template").is_empty());
+    }
+}
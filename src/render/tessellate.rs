@@ -0,0 +1,167 @@
+//! Turns lines into filled outlines, so that their width can vary from point to point.
+
+use std::f32::consts::PI;
+
+/// Steps used to approximate half a circle.
+const ROUND_STEPS: usize = 8;
+/// How much wider than a line its corners may get.
+const MITER_LIMIT: f32 = 2.;
+
+/// Shape of the ends of a line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cap {
+    Butt,
+    Round,
+    Square,
+}
+
+type Vector = (f32, f32);
+
+fn add(a: Vector, b: Vector) -> Vector {
+    (a.0 + b.0, a.1 + b.1)
+}
+
+fn sub(a: Vector, b: Vector) -> Vector {
+    (a.0 - b.0, a.1 - b.1)
+}
+
+fn scale(a: Vector, factor: f32) -> Vector {
+    (a.0 * factor, a.1 * factor)
+}
+
+fn dot(a: Vector, b: Vector) -> f32 {
+    a.0 * b.0 + a.1 * b.1
+}
+
+fn normalize(a: Vector) -> Vector {
+    let length = dot(a, a).sqrt();
+    if length < f32::EPSILON { (0., 0.) } else { scale(a, 1. / length) }
+}
+
+/// Returns the outline of a line through `points`, `widths[i]` wide at point `i`, as a closed polygon.
+///
+/// The polygon may intersect itself where the line does, it is meant to be filled with the nonzero rule.
+pub fn outline(points: &[Vector], widths: &[f32], start: Cap, end: Cap) -> Vec<Vector> {
+    // repeated points have no direction
+    let mut samples: Vec<(Vector, f32)> = Vec::with_capacity(points.len());
+    for (&point, &width) in points.iter().zip(widths) {
+        match samples.last_mut() {
+            Some((last, last_width)) if *last == point => *last_width = last_width.max(width),
+            _ => samples.push((point, width)),
+        }
+    }
+    match samples.as_slice() {
+        [] => return Vec::new(),
+        [(point, width)] => return dot_outline(*point, width / 2., start.max_with(end)),
+        _ => {}
+    }
+
+    let directions: Vec<Vector> = samples.windows(2).map(|pair| normalize(sub(pair[1].0, pair[0].0))).collect();
+    let mut left = Vec::with_capacity(samples.len());
+    let mut right = Vec::with_capacity(samples.len());
+    for (i, &(point, width)) in samples.iter().enumerate() {
+        let before = directions[i.saturating_sub(1)];
+        let after = directions[i.min(directions.len() - 1)];
+        let tangent = match normalize(add(before, after)) {
+            // the line turns back on itself
+            (x, y) if x == 0. && y == 0. => before,
+            tangent => tangent,
+        };
+        let normal = (-tangent.1, tangent.0);
+        // keep the width of both segments at corners
+        let offset = width / 2. / dot(tangent, before).max(1. / MITER_LIMIT);
+        left.push(add(point, scale(normal, offset)));
+        right.push(sub(point, scale(normal, offset)));
+    }
+
+    let (first, last) = (samples[0], samples[samples.len() - 1]);
+    let mut polygon = left;
+    cap(&mut polygon, last.0, directions[directions.len() - 1], last.1 / 2., end);
+    polygon.extend(right.into_iter().rev());
+    cap(&mut polygon, first.0, scale(directions[0], -1.), first.1 / 2., start);
+    polygon
+}
+
+impl Cap {
+    /// The more visible of two caps, for lines of a single point.
+    fn max_with(self, other: Cap) -> Cap {
+        match (self, other) {
+            (Cap::Round, _) | (_, Cap::Round) => Cap::Round,
+            (Cap::Square, _) | (_, Cap::Square) => Cap::Square,
+            _ => Cap::Butt,
+        }
+    }
+}
+
+/// Adds the points of a cap at `point`, going around the outside from the left of `direction` to the right.
+fn cap(polygon: &mut Vec<Vector>, point: Vector, direction: Vector, radius: f32, cap: Cap) {
+    let normal = (-direction.1, direction.0);
+    match cap {
+        Cap::Butt => {}
+        Cap::Square => {
+            let ahead = add(point, scale(direction, radius));
+            polygon.push(add(ahead, scale(normal, radius)));
+            polygon.push(sub(ahead, scale(normal, radius)));
+        }
+        Cap::Round => {
+            for step in 1..ROUND_STEPS {
+                let angle = PI * step as f32 / ROUND_STEPS as f32;
+                let offset = add(scale(normal, angle.cos()), scale(direction, angle.sin()));
+                polygon.push(add(point, scale(offset, radius)));
+            }
+        }
+    }
+}
+
+/// The outline of a line of a single point.
+fn dot_outline(point: Vector, radius: f32, cap: Cap) -> Vec<Vector> {
+    match cap {
+        Cap::Butt => Vec::new(),
+        Cap::Square => [(-1., -1.), (1., -1.), (1., 1.), (-1., 1.)]
+            .iter()
+            .map(|&corner| add(point, scale(corner, radius)))
+            .collect(),
+        Cap::Round => (0..2 * ROUND_STEPS)
+            .map(|step| {
+                let angle = PI * step as f32 / ROUND_STEPS as f32;
+                add(point, (radius * angle.cos(), radius * angle.sin()))
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Vector, b: Vector) -> bool {
+        (a.0 - b.0).abs() < 1e-4 && (a.1 - b.1).abs() < 1e-4
+    }
+
+    #[test]
+    fn it_outlines_lines_of_varying_width() {
+        let polygon = outline(&[(0., 0.), (10., 0.), (10., 0.)], &[2., 4., 6.], Cap::Butt, Cap::Butt);
+        let expected = [(0., 1.), (10., 3.), (10., -3.), (0., -1.)];
+        assert_eq!(polygon.len(), expected.len());
+        assert!(polygon.iter().zip(expected).all(|(&a, b)| close(a, b)));
+    }
+
+    #[test]
+    fn it_adds_caps() {
+        let round = outline(&[(0., 0.), (10., 0.)], &[2., 2.], Cap::Round, Cap::Round);
+        assert_eq!(round.len(), 4 + 2 * (ROUND_STEPS - 1));
+        assert!(round.iter().any(|&p| close(p, (11., 0.))));
+        assert!(round.iter().any(|&p| close(p, (-1., 0.))));
+
+        let square = outline(&[(0., 0.), (10., 0.)], &[2., 2.], Cap::Butt, Cap::Square);
+        assert!(square.iter().any(|&p| close(p, (11., 1.))));
+        assert_eq!(outline(&[(5., 5.)], &[2.], Cap::Round, Cap::Round).len(), 2 * ROUND_STEPS);
+    }
+
+    #[test]
+    fn it_widens_corners() {
+        let polygon = outline(&[(0., 0.), (10., 0.), (10., 10.)], &[2., 2., 2.], Cap::Butt, Cap::Butt);
+        // the outer corner lies on both offset edges
+        assert!(close(polygon[1], (9., 1.)) && close(polygon[4], (11., -1.)));
+    }
+}
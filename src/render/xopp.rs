@@ -0,0 +1,166 @@
+//! Writes pages as [Xournal++](https://xournalpp.github.io/) documents, to continue notebooks on the desktop.
+
+use std::io::{self, Write};
+use flate2::{write::GzEncoder, Compression};
+use snafu::ResultExt;
+use crate::lines::{Line, Page};
+use super::{concat, escape, layer_name, Renderer, RenderOptions, Result, WriteSnafu, Viewport};
+use super::pens::{Blend, Pen};
+
+/// Points per inch, the unit of Xournal++ pages.
+const POINTS_PER_INCH: f32 = 72.;
+
+/// Renders all selected pages into a gzipped `.xopp` file, with one Xournal++ layer per layer.
+///
+/// Lines keep their width at each point, highlighters become Xournal++ highlighters and everything else pens,
+/// except erasers, which are left out.
+#[derive(Debug, Default, Clone)]
+pub struct XoppRenderer {
+    pub title: String,
+    pub background: Option<PdfBackground>,
+}
+
+/// A PDF the pages were annotated on, attached as `{xopp file}.bg.pdf` as Xournal++ expects it.
+#[derive(Debug, Default, Clone)]
+pub struct PdfBackground {
+    /// For each page of the document, the page of the PDF below it counted from 0, with its width and height in points.
+    /// `None` for pages inserted on the tablet.
+    pub pages: Vec<Option<(usize, f32, f32)>>,
+}
+
+impl Renderer for XoppRenderer {
+    fn extension(&self) -> &str {
+        "xopp"
+    }
+
+    fn multi_page(&self) -> bool {
+        true
+    }
+
+    fn render(&self, output: &mut dyn Write, pages: &[(usize, &Page)], options: &RenderOptions) -> Result<()> {
+        let mut output = GzEncoder::new(output, Compression::default());
+        self.write(&mut output, pages, options).context(WriteSnafu)?;
+        output.finish().context(WriteSnafu)?;
+        Ok(())
+    }
+}
+
+impl XoppRenderer {
+    fn write(&self, output: &mut dyn Write, pages: &[(usize, &Page)], options: &RenderOptions) -> io::Result<()> {
+        writeln!(output, r#"<?xml version="1.0" standalone="no"?>"#)?;
+        writeln!(output, r#"<xournal creator="unremarkable-notes" fileversion="4">"#)?;
+        writeln!(output, "<title>{}</title>", escape(&self.title))?;
+        // only the first page of a background names its file
+        let mut attached = false;
+        for &(index, page) in pages {
+            let original = self.background.as_ref().and_then(|b| b.pages.get(index).copied().flatten());
+            let (width, height, transform) = match original {
+                Some((_, width, height)) => {
+                    let screen = Viewport {
+                        bounds: options.device.bounds(),
+                        scale: 1.,
+                        orientation: options.orientation.unwrap_or_default(),
+                        device: options.device,
+                    };
+                    let (screen_width, screen_height) = screen.size();
+                    let scale = (width / screen_width).max(height / screen_height);
+                    (width, height, concat([scale, 0., 0., scale, 0., 0.], screen.transform()))
+                }
+                None => {
+                    let viewport = Viewport::new(index, page, options);
+                    let viewport = Viewport { scale: viewport.scale * POINTS_PER_INCH / options.device.dpi(), ..viewport };
+                    let (width, height) = viewport.size();
+                    (width, height, viewport.transform())
+                }
+            };
+
+            writeln!(output, r#"<page width="{}" height="{}">"#, width, height)?;
+            match original {
+                Some((number, _, _)) if !attached => {
+                    attached = true;
+                    writeln!(output, r#"<background type="pdf" domain="attach" filename="bg.pdf" pageno="{}"/>"#, number + 1)?;
+                }
+                Some((number, _, _)) => writeln!(output, r#"<background type="pdf" pageno="{}"/>"#, number + 1)?,
+                None => writeln!(output, r##"<background type="solid" color="#ffffffff" style="plain"/>"##)?,
+            }
+            if page.layers.is_empty() {
+                writeln!(output, "<layer/>")?;
+            }
+            for (index, layer) in page.layers.iter().enumerate() {
+                writeln!(output, r#"<layer name="{}">"#, escape(&layer_name(index, layer)))?;
+                for line in &layer.lines {
+                    write_stroke(output, line, transform, options)?;
+                }
+                writeln!(output, "</layer>")?;
+            }
+            writeln!(output, "</page>")?;
+        }
+        writeln!(output, "</xournal>")
+    }
+}
+
+/// Writes a line as a stroke with the width of each of its segments, nothing for erasers and selections.
+fn write_stroke(output: &mut dyn Write, line: &Line, transform: [f32; 6], options: &RenderOptions) -> io::Result<()> {
+    let pen = match Pen::new(line, &options.palette) {
+        Some(pen) if !line.points.is_empty() => pen,
+        _ => return Ok(()),
+    };
+    let tool = match pen.blend {
+        Blend::Multiply => "highlighter",
+        Blend::Normal => "pen",
+        // Xournal++ erases by splitting the strokes below, which are exported whole
+        Blend::Erase => return Ok(()),
+    };
+    let [a, b, c, d, e, f] = transform;
+    let scale = (a * d - b * c).abs().sqrt();
+    let segments = pen.segments(line);
+    let opacity = segments.iter().map(|s| s.opacity).sum::<f32>() / segments.len() as f32;
+    let [red, green, blue] = pen.color.map(|c| (c * 255.).round() as u8);
+    // the nominal width, taken from the segment leading to the first point, followed by the width of each segment
+    let widths: Vec<String> = segments.iter().map(|s| (s.width * scale).to_string()).collect();
+    write!(
+        output,
+        r##"<stroke tool="{}" color="#{:02x}{:02x}{:02x}{:02x}" width="{}">"##,
+        tool, red, green, blue, (opacity * 255.).round() as u8, widths.join(" ")
+    )?;
+    // strokes need at least two points, dots repeat theirs
+    let repeat = if line.points.len() == 1 { 2 } else { 1 };
+    let coordinates: Vec<String> = line.points
+        .iter()
+        .flat_map(|point| std::iter::repeat_n(point, repeat))
+        .map(|point| format!("{} {}", a * point.x + c * point.y + e, b * point.x + d * point.y + f))
+        .collect();
+    writeln!(output, "{}</stroke>", coordinates.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use crate::lines::{BrushType, Color, Layer, Point};
+
+    #[test]
+    fn it_writes_strokes_with_widths_per_segment() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let point = |x, pressure| Point { x, y: 226., speed: 0., direction: 0., width: 4., pressure };
+        let pen = Line { brush_type: BrushType::Ballpoint, color: Color::Blue, brush_base_size: 2., points: vec![point(0., 0.2), point(113., 0.5), point(226., 1.)] };
+        let highlight = Line { brush_type: BrushType::Highlighter, color: Color::Yellow, brush_base_size: 2., points: vec![point(0., 1.)] };
+        let eraser = Line { brush_type: BrushType::Eraser, ..pen.clone() };
+        let page = Page { layers: vec![Layer { name: Some("Notes & Co".to_string()), lines: vec![pen, highlight, eraser] }], text: None };
+        let renderer = XoppRenderer { title: "Notes".to_string(), background: None };
+
+        let mut output = Vec::new();
+        renderer.render(&mut output, &[(0, &page)], &RenderOptions::default())?;
+        let mut xml = String::new();
+        flate2::read::GzDecoder::new(output.as_slice()).read_to_string(&mut xml)?;
+
+        assert!(xml.contains(r#"<layer name="Notes &amp; Co">"#));
+        let stroke = xml.lines().find(|l| l.contains(r#"tool="pen""#)).ok_or("no pen stroke")?;
+        let widths = stroke.split("width=\"").nth(1).and_then(|w| w.split('"').next()).ok_or("no widths")?;
+        assert_eq!(widths.split(' ').count(), 3);
+        // one inch on the tablet is one inch in Xournal++
+        assert!(stroke.ends_with(">0 72 36 72 72 72</stroke>"), "{}", stroke);
+        assert!(xml.contains(r#"tool="highlighter""#));
+        assert_eq!(xml.matches("<stroke").count(), 2, "erasers are left out");
+        Ok(())
+    }
+}
@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
-use super::item::Item;
+use super::item::{Item, Tag};
+use super::KeyOrder;
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -11,10 +12,26 @@ pub struct Collection {
     pub content: Content,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
 pub struct Content {
-    pub tags: Vec<String>,
+    pub tags: Vec<Tag>,
+
+    /// Keys we don't know about, preserved when writing the content back.
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+    #[serde(skip)]
+    keys: Vec<String>,
+}
+
+impl KeyOrder for Content {
+    fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    fn set_keys(&mut self, keys: Vec<String>) {
+        self.keys = keys;
+    }
 }
 
 impl<'a> core::fmt::Display for Collection {
@@ -1,8 +1,11 @@
-use super::{Store, item::Item, error::*};
+use super::{Store, KeyOrder, item::{Item, Tag}, error::*, highlight::{Highlight, Highlights}};
 use snafu::ResultExt;
 use serde::{Deserialize, Serialize};
-use std::path::Path;
-use lines_are_rusty::{Page, LinesData, render_svg};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use crate::lines::{Page, text::Text};
+use crate::render::{AnnotatedPdfRenderer, Crop, Orientation, Palette, PngRenderer, Renderer, RenderOptions, Template, XoppRenderer, Zoom, xopp::PdfBackground};
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -14,17 +17,27 @@ pub struct Document {
     pub content: Content,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
+/// Contents of `{notebook_uuid}.content`.
+///
+/// Which keys exist differs between firmware versions, missing ones take their default
+/// and are only written back if the file contained them.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
 pub struct Content {
     pub cover_page_number: isize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub custom_zoom_center_x: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub custom_zoom_center_y: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub custom_zoom_scale: Option<f64>,
     pub document_metadata: serde_json::Value,
     pub dummy_document: bool,
     pub extra_metadata: serde_json::Value,
     pub font_name: String,
     pub format_version: usize,
     pub file_type: String,
-    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub last_opened_page: Option<usize>,
     pub line_height: i32,
     pub margins: usize,
@@ -33,14 +46,151 @@ pub struct Content {
     pub page_count: usize,
     pub pages: Vec<uuid::Uuid>,
     pub page_tags: Vec<serde_json::Value>,
-    #[serde(default)]
     pub redirection_page_map: Vec<isize>,
     pub size_in_bytes: String,
-    pub tags: Vec<String>,
+    pub tags: Vec<Tag>,
     pub text_alignment: String,
     pub text_scale: usize,
+    /// How the tablet fits pages to the screen, such as `bestFit` or `customZoom`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zoom_mode: Option<String>,
+
+    /// Keys we don't know about, such as `cPages` or `customZoomPageHeight`, preserved when writing the content back.
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+    #[serde(skip)]
+    keys: Vec<String>,
 }
 
+impl KeyOrder for Content {
+    fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    fn set_keys(&mut self, keys: Vec<String>) {
+        self.keys = keys;
+    }
+}
+
+impl Content {
+    /// Content of a newly created document with `page_count` fresh pages, in the format of firmware 2.x,
+    /// which later firmware migrates.
+    pub fn new(file_type: &str, page_count: usize) -> Self {
+        Self {
+            cover_page_number: 0,
+            document_metadata: serde_json::json!({}),
+            extra_metadata: serde_json::json!({}),
+            file_type: file_type.to_string(),
+            format_version: 1,
+            line_height: -1,
+            margins: 125,
+            orientation: "portrait".to_string(),
+            original_page_count: page_count as i32,
+            page_count,
+            pages: (0..page_count).map(|_| uuid::Uuid::new_v4()).collect(),
+            redirection_page_map: (0..page_count as isize).collect(),
+            size_in_bytes: "0".to_string(),
+            text_alignment: "justify".to_string(),
+            text_scale: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Firmware 3.x replaced `pages` and `redirectionPageMap` with `cPages`, which holds
+    /// pages in a CRDT sequence ordered by their `idx`. Fills both from it, skipping deleted pages.
+    pub(crate) fn apply_c_pages(&mut self) {
+        if !self.pages.is_empty() {
+            return;
+        }
+        let pages = self.extra
+            .get("cPages")
+            .and_then(|c| c.get("pages"))
+            .and_then(|p| p.as_array());
+        let mut pages: Vec<(&str, uuid::Uuid, isize)> = pages
+            .into_iter()
+            .flatten()
+            .filter(|page| page.get("deleted").is_none())
+            .filter_map(|page| {
+                let index = page.get("idx")?.get("value")?.as_str()?;
+                let id = uuid::Uuid::parse_str(page.get("id")?.as_str()?).ok()?;
+                let redirection = page
+                    .get("redir")
+                    .and_then(|r| r.get("value"))
+                    .and_then(|v| v.as_i64())
+                    .map_or(-1, |v| v as isize);
+                Some((index, id, redirection))
+            })
+            .collect();
+        pages.sort();
+        self.pages = pages.iter().map(|(_, id, _)| *id).collect();
+        self.redirection_page_map = pages.iter().map(|(_, _, redirection)| *redirection).collect();
+    }
+
+    /// The page of the original PDF or EPub shown as the page at `index`, counted from 0,
+    /// `None` for pages inserted on the tablet. Pages of notebooks are their own originals.
+    pub fn original_page(&self, index: usize) -> Option<usize> {
+        if self.file_type == "notebook" {
+            return Some(index);
+        }
+        match self.redirection_page_map.get(index) {
+            Some(&original) => usize::try_from(original).ok(),
+            None => Some(index),
+        }
+    }
+
+    /// The custom zoom the document was last viewed at, if it was.
+    pub fn zoom(&self) -> Option<Zoom> {
+        if self.zoom_mode.as_deref() != Some("customZoom") {
+            return None;
+        }
+        Some(Zoom {
+            center: (self.custom_zoom_center_x? as f32, self.custom_zoom_center_y? as f32),
+            scale: self.custom_zoom_scale? as f32,
+        })
+    }
+
+    /// The template of each page in `cPages`, `None` for pages without one or if there is no `cPages`.
+    pub fn page_templates(&self) -> Vec<Option<String>> {
+        self.c_page_values("template", |value| value.as_str().map(String::from))
+    }
+
+    /// How far each page extends down, from its `verticalScroll` in `cPages`.
+    ///
+    /// Firmware 3.x lets notebook pages scroll beyond the screen, older files have no extents.
+    pub fn page_heights(&self) -> Vec<Option<f32>> {
+        self.c_page_values("verticalScroll", |value| value.as_f64().map(|height| height as f32))
+    }
+
+    /// The value of the register `key` of each page in `cPages`, converted with `convert`.
+    fn c_page_values<T>(&self, key: &str, convert: impl Fn(&serde_json::Value) -> Option<T>) -> Vec<Option<T>> {
+        let pages = self.extra
+            .get("cPages")
+            .and_then(|c| c.get("pages"))
+            .and_then(|p| p.as_array());
+        let mut values: HashMap<&str, T> = pages
+            .into_iter()
+            .flatten()
+            .filter_map(|page| Some((page.get("id")?.as_str()?, convert(page.get(key)?.get("value")?)?)))
+            .collect();
+        self.pages
+            .iter()
+            .map(|id| values.remove(id.to_string().as_str()))
+            .collect()
+    }
+}
+
+/// Contents of `{notebook_uuid}/{page_uuid}-metadata.json`, which names the layers of version 5 pages.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct PageMetadata {
+    layers: Vec<LayerMetadata>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct LayerMetadata {
+    name: Option<String>,
+}
 
 #[derive(Debug)]
 pub enum DocumentType {
@@ -57,39 +207,285 @@ impl<'a> core::fmt::Display for Document {
 
 // TODO maybe move to shared trait for Notebook, PDF, Epub.
 impl Document {
-    pub fn to_pdf(&self, store: &dyn Store, path: &Path) -> Result<()> {
-        let parsed = self.pages(store)?;
-        crate::render::render(path, parsed)
-            .context(WriteFileSnafu { path })?;
+    /// Renders the annotations of the pages of a PDF document selected in `options` on top of the original PDF.
+    pub fn to_annotated_pdf(&self, store: &dyn Store, path: &Path, options: &RenderOptions) -> Result<()> {
+        if self.content.file_type != "pdf" {
+            return InvalidItemTypeSnafu { id: self.metadata.id.to_string(), type_: &self.content.file_type }.fail();
+        }
+        let original_path = &Path::new(&self.metadata.id.to_string()).with_extension("pdf");
+        let original = store.get_file(original_path)?;
+        let original = lopdf::Document::load_from(original)
+            .context(ReadPdfSnafu { path: original_path })?;
+        let renderer = AnnotatedPdfRenderer { original, redirection_page_map: self.content.redirection_page_map.clone() };
+        self.render(store, &renderer, path, options)?;
+        Ok(())
+    }
+
+    /// Writes the pages selected in `options` to a gzipped Xournal++ document at `path`.
+    ///
+    /// PDF documents keep their original as background, copied to `{path}.bg.pdf` next to it.
+    pub fn to_xopp(&self, store: &dyn Store, path: &Path, options: &RenderOptions) -> Result<()> {
+        let mut background = None;
+        let mut original = Vec::new();
+        if self.content.file_type == "pdf" {
+            let original_path = &Path::new(&self.metadata.id.to_string()).with_extension("pdf");
+            store.get_file(original_path)?.read_to_end(&mut original).context(ReadFileSnafu { path: original_path })?;
+            let sizes = lopdf::Document::load_mem(&original)
+                .and_then(|document| crate::render::pdf::page_sizes(&document))
+                .context(ReadPdfSnafu { path: original_path })?;
+            let pages = (0..self.content.pages.len())
+                .map(|index| {
+                    let page = self.content.redirection_page_map.get(index).copied().unwrap_or(index as isize);
+                    let page = usize::try_from(page).ok()?;
+                    sizes.get(page).map(|&(width, height)| (page, width, height))
+                })
+                .collect();
+            background = Some(PdfBackground { pages });
+        }
+        let attach = background.is_some();
+        let renderer = XoppRenderer { title: self.metadata.visible_name.clone(), background };
+        self.render(store, &renderer, path, options)?;
+        if attach {
+            let mut attached = path.as_os_str().to_owned();
+            attached.push(".bg.pdf");
+            let attached = PathBuf::from(attached);
+            std::fs::write(&attached, &original).context(WriteFileSnafu { path: &attached })?;
+        }
         Ok(())
     }
 
-    pub fn to_svg(&self, store: &dyn Store, path: &Path, page: usize) -> Result<()> {
-        let mut output = std::fs::File::create(path).context(WriteFileSnafu {path})?;
-        let pages = self.pages(store)?;
-        let page = pages.get(page).ok_or(Error::InvalidPage { id: self.metadata.id, page })?;
-        let auto_crop = false;
-        let layer_colors = Default::default();
-        let distance_threshold = 2.0;
-        let template = None;
-        let debug_dump = true;
-        let rendered = render_svg(&mut output, page, auto_crop, layer_colors, distance_threshold, template, debug_dump)
-            .context(ParseLinesSnafu {path})?;
-        Ok(rendered)
+    /// Renders the page `page`, counted from 0, on top of its template to a PNG file at `path`,
+    /// with `dpi` pixels per inch of the tablet's screen.
+    pub fn to_png(&self, store: &dyn Store, path: &Path, page: usize, dpi: f32) -> Result<()> {
+        let options = RenderOptions { templates: self.load_templates(store, None)?, ..RenderOptions::default() };
+        let options = &self.with_defaults(&RenderOptions { scale: options.device.scale_at(dpi), ..options });
+        let pages = self.prepared_pages(store, options)?;
+        let selected = pages.get(page).ok_or(Error::InvalidPage { id: self.metadata.id, page })?;
+
+        let mut output = std::io::BufWriter::new(std::fs::File::create(path).context(WriteFileSnafu { path })?);
+        PngRenderer.render(&mut output, &[(page, selected)], options).context(RenderSnafu { path })?;
+        output.flush().context(WriteFileSnafu { path })
+    }
+
+    /// Renders the pages selected in `options` with `renderer` and returns the paths written to.
+    ///
+    /// Multi-page formats are written to the file `path`, others to one file per page in the directory `path`.
+    pub fn render(&self, store: &dyn Store, renderer: &dyn Renderer, path: &Path, options: &RenderOptions) -> Result<Vec<PathBuf>> {
+        let options = &self.with_defaults(options);
+        let pages = self.prepared_pages(store, options)?;
+        let indexes = options.pages.as_ref().map_or_else(|| (0..pages.len()).collect(), |ranges| ranges.indexes(pages.len()));
+        let mut selected = Vec::new();
+        for index in indexes {
+            let page = pages.get(index).ok_or(Error::InvalidPage { id: self.metadata.id, page: index })?;
+            selected.push((index, page));
+        }
+
+        let mut paths = Vec::new();
+        let mut write = |path: PathBuf, pages: &[(usize, &Page)]| -> Result<()> {
+            let mut output = std::io::BufWriter::new(std::fs::File::create(&path).context(WriteFileSnafu { path: &path })?);
+            renderer.render(&mut output, pages, options).context(RenderSnafu { path: &path })?;
+            output.flush().context(WriteFileSnafu { path: &path })?;
+            paths.push(path);
+            Ok(())
+        };
+        if renderer.multi_page() {
+            write(path.to_path_buf(), &selected)?;
+        } else {
+            for page in &selected {
+                write(self.page_path(path, page.0, renderer.extension()), std::slice::from_ref(page))?;
+            }
+        }
+        Ok(paths)
+    }
 
+    /// Returns the pages with the layers selected in `options` and their geometry passes applied.
+    fn prepared_pages(&self, store: &dyn Store, options: &RenderOptions) -> Result<Vec<Page>> {
+        Ok(self.pages(store)?
+            .iter()
+            .map(|page| match &options.layers {
+                Some(layers) => options.geometry.apply(&layers.apply(page)),
+                None => options.geometry.apply(page),
+            })
+            .collect())
     }
+
+    /// Completes `options` with the orientation, zoom and page extents of this document, unless they specify them.
+    fn with_defaults(&self, options: &RenderOptions) -> RenderOptions {
+        let orientation = options.orientation.unwrap_or_else(|| Orientation::from_content(&self.content.orientation));
+        let page_heights = match options.page_heights.is_empty() {
+            true => self.content.page_heights(),
+            false => options.page_heights.clone(),
+        };
+        RenderOptions {
+            orientation: Some(orientation),
+            zoom: options.zoom.or_else(|| self.content.zoom()),
+            page_heights,
+            ..options.clone()
+        }
+    }
+
+    /// Path of the file a page is rendered to: the visible name and the page number, counted from 1.
+    pub fn page_path(&self, directory: &Path, page: usize, extension: &str) -> PathBuf {
+        let name = self.metadata.visible_name.replace('/', "-");
+        directory.join(format!("{}-{:03}.{}", name, page + 1, extension))
+    }
+
+    /// Writes highlights, grouped by page, as Markdown with YAML front matter.
+    ///
+    /// If `notes` is set, handwritten notes are embedded as SVG below the highlights of their page.
+    pub fn to_markdown(&self, store: &dyn Store, path: &Path, notes: bool, palette: &Palette) -> Result<()> {
+        let mut output = std::fs::File::create(path).context(WriteFileSnafu { path })?;
+        let highlights = self.highlights(store)?;
+        let pages = if notes { self.pages(store)? } else { Vec::new() };
+
+        let mut markdown = String::from("---\n");
+        markdown.push_str(&format!("title: {}\n", yaml_string(&self.metadata.visible_name)));
+        if let Some(modified) = crate::utils::format_timestamp(&self.metadata.last_modified) {
+            markdown.push_str(&format!("last_modified: {}\n", modified));
+        }
+        let tags: Vec<String> = self.content.tags.iter().map(|t| yaml_string(t.name())).collect();
+        markdown.push_str(&format!("tags: [{}]\n", tags.join(", ")));
+        markdown.push_str(&format!("---\n\n# {}\n", self.metadata.visible_name));
+
+        for page in 0..self.content.pages.len() {
+            let on_page: Vec<&Highlight> = highlights.iter().filter(|h| h.page == page).collect();
+            let ink = pages.get(page).filter(|p| p.layers.iter().any(|l| !l.lines.is_empty()));
+            if on_page.is_empty() && ink.is_none() {
+                continue;
+            }
+            // numbered as in the original, pages inserted on the tablet by the page they follow
+            let (heading, reference) = match self.content.original_page(page) {
+                Some(original) => (format!("Page {}", original + 1), format!("p. {}", original + 1)),
+                None => {
+                    let after = (0..page).rev().find_map(|before| self.content.original_page(before));
+                    let label = after.map_or_else(|| "before p. 1".to_string(), |after| format!("after p. {}", after + 1));
+                    (format!("Page inserted {}", label), format!("page inserted {}", label))
+                }
+            };
+            markdown.push_str(&format!("\n## {}\n", heading));
+            for highlight in on_page {
+                markdown.push('\n');
+                for line in highlight.text.lines() {
+                    markdown.push_str(&format!("> {}\n", line));
+                }
+                markdown.push_str(&format!("> — {}\n", reference));
+            }
+            if let Some(ink) = ink {
+                let mut svg = Vec::new();
+                let options = RenderOptions { palette: palette.clone(), crop: Crop::Content, ..RenderOptions::default() };
+                let ink = options.geometry.apply(ink);
+                crate::render::SvgRenderer.render(&mut svg, &[(page, &ink)], &options).context(RenderSnafu { path })?;
+                markdown.push('\n');
+                markdown.push_str(&String::from_utf8_lossy(&svg));
+            }
+        }
+
+        output.write_all(markdown.as_bytes()).context(WriteFileSnafu { path })
+    }
+
+    /// Returns the typed text of each page, empty for pages without any.
+    pub fn text(&self, store: &dyn Store) -> Result<Vec<Text>> {
+        Ok(self.pages(store)?
+            .into_iter()
+            .map(|page| page.text.unwrap_or_default())
+            .collect())
+    }
+
     pub fn pages(&self, store: &dyn Store) -> Result<Vec<Page>> {
         let mut pages = Vec::new();
         for page_id in &self.content.pages {
             let path = &Path::new(&self.metadata.id.to_string())
                 .join(&page_id.to_string())
                 .with_extension("rm");
-            let mut file = store.get_file(path)?;
-            pages.append(&mut LinesData::parse(&mut file).context(ParseLinesSnafu { path: &path })?.pages)
+            // pages without annotations have no lines file
+            let mut file = match optional_file(store, path)? {
+                Some(file) => file,
+                None => {
+                    pages.push(Page::default());
+                    continue;
+                }
+            };
+            let mut parsed = crate::lines::parse(&mut file).context(ParseLinesSnafu { path: &path })?;
+            let metadata_path = &Path::new(&self.metadata.id.to_string()).join(format!("{}-metadata.json", page_id));
+            if let Some(file) = optional_file(store, metadata_path)? {
+                let metadata: PageMetadata = serde_json::from_reader(file).context(ParseJsonSnafu { path: metadata_path })?;
+                for page in &mut parsed {
+                    for (layer, named) in page.layers.iter_mut().zip(&metadata.layers) {
+                        layer.name = layer.name.take().or_else(|| named.name.clone());
+                    }
+                }
+            }
+            pages.append(&mut parsed)
         }
         Ok(pages)
     }
 
+    /// Returns the name of the template of each page, such as `P Lines small`.
+    ///
+    /// Firmware 3.x keeps them in `cPages`, older firmware in `.pagedata`.
+    /// Pages missing from both, or all pages if there is neither, are `Blank`.
+    pub fn templates(&self, store: &dyn Store) -> Result<Vec<String>> {
+        let path = &Path::new(&self.metadata.id.to_string()).with_extension("pagedata");
+        let mut pagedata = String::new();
+        if let Some(mut file) = optional_file(store, path)? {
+            file.read_to_string(&mut pagedata).context(ReadFileSnafu { path })?;
+        }
+        let mut names = pagedata.lines().map(str::trim);
+        Ok(self.content.page_templates()
+            .into_iter()
+            .map(|c_page| {
+                let name = names.next().filter(|name| !name.is_empty());
+                c_page.as_deref().or(name).unwrap_or("Blank").to_string()
+            })
+            .collect())
+    }
+
+    /// Loads the template of each page from a copy of the device's templates directory,
+    /// falling back to the built-in ones for templates missing there.
+    ///
+    /// Each template is loaded once, pages using the same one share its image.
+    pub fn load_templates(&self, store: &dyn Store, directory: Option<&Path>) -> Result<Vec<Template>> {
+        let mut loaded: HashMap<String, Template> = HashMap::new();
+        let mut templates = Vec::new();
+        for name in self.templates(store)? {
+            if !loaded.contains_key(&name) {
+                let template = match directory {
+                    Some(directory) => Template::load(directory, &name).context(ReadFileSnafu { path: directory.join(&name) })?,
+                    None => Template::default(),
+                };
+                let template = match template.is_empty() {
+                    true => Template::builtin(&name).unwrap_or_default(),
+                    false => template,
+                };
+                loaded.insert(name.clone(), template);
+            }
+            templates.push(loaded[&name].clone());
+        }
+        Ok(templates)
+    }
+
+    /// Returns the highlights of all pages in reading order.
+    pub fn highlights(&self, store: &dyn Store) -> Result<Vec<Highlight>> {
+        let directory = Path::new(&self.metadata.id.to_string()).with_extension("highlights");
+        let mut highlights = Vec::new();
+        for (page, page_id) in self.content.pages.iter().enumerate() {
+            let path = &directory.join(page_id.to_string()).with_extension("json");
+            let file = match optional_file(store, path)? {
+                Some(file) => file,
+                None => continue,
+            };
+            let parsed: Highlights = serde_json::from_reader(file).context(ParseJsonSnafu { path })?;
+            let mut on_page: Vec<Highlight> = parsed.highlights
+                .into_iter()
+                .flatten()
+                .map(|highlight| Highlight { page, ..highlight })
+                .collect();
+            on_page.sort_by_key(|highlight| highlight.start);
+            highlights.append(&mut on_page);
+        }
+        Ok(highlights)
+    }
+
 //impl FileType {
 //    pub fn content(&self) -> Result<ContentType> {
 //        let item = self.item();
@@ -113,6 +509,19 @@ impl Document {
 
 }
 
+/// Quotes a string for YAML, which accepts JSON strings.
+fn yaml_string(value: &str) -> String {
+    serde_json::Value::from(value).to_string()
+}
+
+/// Opens a file which only exists for some pages.
+fn optional_file(store: &dyn Store, path: &Path) -> Result<Option<std::fs::File>> {
+    match store.get_file(path) {
+        Err(Error::ReadFile { source, .. }) if source.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        file => file.map(Some),
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Notebook {}
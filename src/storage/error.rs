@@ -19,11 +19,31 @@ pub enum Error {
         source: std::io::Error,
         path: PathBuf,
     },
+    #[snafu(display("Unable to remove {}: {}", path.display(), source))]
+    RemoveFile {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+    #[snafu(display("Unable to read pdf at {}: {}", path.display(), source))]
+    ReadPdf {
+        source: lopdf::Error,
+        path: PathBuf,
+    },
+    #[snafu(display("Unable to render {}: {}", path.display(), source))]
+    Render {
+        source: crate::render::Error,
+        path: PathBuf,
+    },
     #[snafu(display("Unable to parse json at {}: {}", path.display(), source))]
     ParseJson {
         source: serde_json::Error,
         path: PathBuf,
     },
+    #[snafu(display("Unable to serialize json at {}: {}", path.display(), source))]
+    SerializeJson {
+        source: serde_json::Error,
+        path: PathBuf,
+    },
     #[snafu(display("Unable to read xochitl store at {}: {}", path.display(), source))]
     ReadStore {
         source: std::io::Error,
@@ -31,7 +51,7 @@ pub enum Error {
     },
     #[snafu(display("Unable to parse remarkable lines at {}: {}", path.display(), source))]
     ParseLines {
-        source: lines_are_rusty::Error,
+        source: crate::lines::Error,
         path: PathBuf,
     },
     #[snafu(display("Invalid uuid: {}", source))]
@@ -39,6 +59,20 @@ pub enum Error {
 
     #[snafu(display("Invalid type for #{}: {}", id, type_))]
     InvalidItemType { id: String, type_: String },
+    #[snafu(display("Unable to move #{} into #{}, which is the item itself or within it", id, parent))]
+    MoveIntoItself { id: String, parent: uuid::Uuid },
+    #[snafu(display("Unable to restore #{}, which is not in the trash", id))]
+    NotTrashed { id: String },
+
+    #[snafu(display("Unable to create notebook {} without any pages", name))]
+    EmptyNotebook { name: String },
+    #[snafu(display("Unable to import {}, only pdf and epub files are supported", path.display()))]
+    UnsupportedFile { path: PathBuf },
+
+    #[snafu(display("No item at {}", path))]
+    PathNotFound { path: String },
+    #[snafu(display("{} is ambiguous, it could be any of {:?}", path, ids))]
+    AmbiguousPath { path: String, ids: Vec<uuid::Uuid> },
 }
 
 pub type Result<T> = core::result::Result<T, Error>;
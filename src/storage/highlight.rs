@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+use crate::lines::Color;
+
+/// A passage of text marked with the highlighter in a PDF or EPub.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Highlight {
+    /// Index of the page in [`Content::pages`](super::document::Content::pages).
+    #[serde(skip)]
+    pub page: usize,
+    pub text: String,
+    #[serde(default = "default_color")]
+    pub color: Color,
+    /// Offset of the first highlighted character on the page.
+    pub start: usize,
+    pub length: usize,
+    pub rects: Vec<Rect>,
+}
+
+/// An area covered by a highlight, in screen pixels.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// Contents of `{notebook_uuid}.highlights/{page_uuid}.json`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Highlights {
+    pub highlights: Vec<Vec<Highlight>>,
+}
+
+/// Highlights made before colors were introduced are yellow.
+fn default_color() -> Color {
+    Color::Yellow
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_highlights() -> serde_json::Result<()> {
+        let json = r#"{
+            "highlights": [[
+                {
+                    "color": 4,
+                    "length": 11,
+                    "rects": [{ "height": 23.5, "width": 104.5, "x": 267.75, "y": 355.0 }],
+                    "start": 1542,
+                    "text": "Ownership 1"
+                },
+                {
+                    "length": 2,
+                    "rects": [],
+                    "start": 12,
+                    "text": "is"
+                }
+            ]]
+        }"#;
+        let parsed: Highlights = serde_json::from_str(json)?;
+        let highlights = &parsed.highlights[0];
+        assert_eq!(highlights[0].text, "Ownership 1");
+        assert_eq!(highlights[0].color, Color::Green);
+        assert_eq!(highlights[0].rects[0].x, 267.75);
+        assert_eq!(highlights[1].color, Color::Yellow);
+        Ok(())
+    }
+}
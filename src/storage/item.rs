@@ -1,30 +1,97 @@
 use serde::{Deserialize, Serialize};
 
-use crate::utils::deserialize_parent;
-use super::{Collection, Document};
+use crate::utils::{deserialize_parent, serialize_parent};
+use super::{Collection, Document, KeyOrder};
 
+/// Parent of items in the trash, stored as `"trash"` by `xochitl`.
+pub const TRASH: uuid::Uuid = uuid::Uuid::nil();
+
+/// Contents of `{notebook_uuid}.metadata`.
+///
+/// Firmware 3.x dropped `deleted`, `metadatamodified`, `modified`, `synced` and `version`,
+/// they default to `false` and `0` and are only written back if the file contained them.
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Item {
-    #[serde(skip_deserializing)]
+    #[serde(skip)]
     pub id: uuid::Uuid,
     #[serde(rename="type")]
     pub type_: String,
+    #[serde(default)]
     pub deleted: bool,
     pub last_modified: String,
+    #[serde(default)]
     pub metadatamodified: bool,
+    #[serde(default)]
     pub modified: bool,
-    #[serde(deserialize_with = "deserialize_parent")]
+    #[serde(default, deserialize_with = "deserialize_parent", serialize_with = "serialize_parent")]
     pub parent: Option<uuid::Uuid>,
     pub pinned: bool,
+    #[serde(default)]
     pub synced: bool,
-    pub version: u8,
+    #[serde(default)]
+    pub version: u32,
 
     pub visible_name: String,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub last_opened: Option<String>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub last_opened_page: Option<u16>,
+
+    /// Keys we don't know about, preserved when writing the item back.
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+    #[serde(skip)]
+    keys: Vec<String>,
+}
+
+/// A tag on a document or collection, firmware 3.x added the time it was tagged.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Tag {
+    Name(String),
+    Timestamped { name: String, timestamp: u64 },
+}
+
+impl Item {
+    /// Metadata of a newly created item, not yet synced.
+    pub fn new(type_: &str, visible_name: &str, parent: Option<uuid::Uuid>) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4(),
+            type_: type_.to_string(),
+            deleted: false,
+            last_modified: crate::utils::timestamp_now(),
+            metadatamodified: false,
+            modified: false,
+            parent,
+            pinned: false,
+            synced: false,
+            version: 0,
+            visible_name: visible_name.to_string(),
+            last_opened: None,
+            last_opened_page: None,
+            extra: serde_json::Map::new(),
+            keys: Vec::new(),
+        }
+    }
+}
+
+impl Tag {
+    pub fn name(&self) -> &str {
+        match self {
+            Tag::Name(name) | Tag::Timestamped { name, .. } => name,
+        }
+    }
+}
+
+impl KeyOrder for Item {
+    fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    fn set_keys(&mut self, keys: Vec<String>) {
+        self.keys = keys;
+    }
 }
 
 #[derive(Debug)]
@@ -10,6 +10,8 @@
 //!   Can be queried for metadata or `.try_into()`´ed into a `Collection` or `Document`.
 //! - [`Collection`](collection::Collection): A "directory" in `xochitl`.
 //! - [`Document`](document::Document): An abstract document with an associated Trait
+//! - [`Highlight`](highlight::Highlight): A passage of text marked in a PDF or EPub.
+//! - [`Tree`](tree::Tree): All items nested by their parents, addressable by paths such as `/Work/Papers/foo`.
 //!
 //! ## Usage
 //!
@@ -25,12 +27,13 @@
 //!
 //! ## Files
 //! - `{notebook_uuid}.metadata`: Deserialized to `Item`, Entry metadata, such as its name and whether it is a `Collection` or a `Document`.
+//!     Items in the trash have the parent `"trash"`, items removed from the trash are marked as `deleted` until synced.
 //! - `{notebook_uuid}.content`:
-//! - `{notebook_uuid}.pagedata`:
+//! - `{notebook_uuid}.pagedata`: The name of the template of each page, one per line.
 //! - `{notebook_uuid}/{page_uuid}.rm`:
-//! - `{notebook_uuid}/{page_uuid}-metadata.json`:
+//! - `{notebook_uuid}/{page_uuid}-metadata.json`: The names of the layers of that page, for version 5 lines files.
 //! - `{notebook_uuid}.thumbnails/{page_uuid}.jpg`:
-//! - `{notebook_uuid}.highlights/{page_uuid}.json`:
+//! - `{notebook_uuid}.highlights/{page_uuid}.json`: Deserialized to [`Highlights`](highlight::Highlights), text marked on that page.
 //! - `{notebook_uuid}.pdf`:
 //! - `{notebook_uuid}.epub`:
 //! - `{notebook_uuid}.epubindex`:
@@ -48,12 +51,14 @@
 //! ## Remarkable Lines
 //! Remarkable stores drawings in a custom [file format](https://plasma.ninja/blog/devices/remarkable/binary/format/2017/12/26/reMarkable-lines-file-format.html),
 //! which uses the `.rm` extension. We use [lines-are-rusty](https://github.com/ax3l/lines-are-rusty)
-//! to parse and render them.
+//! to parse them up to version 5, and our own [parser](crate::lines::v6) for version 6, as written by firmware 3.x.
 
 pub mod error;
 pub mod item;
 pub mod collection;
 pub mod document;
+pub mod highlight;
+pub mod tree;
 
 #[doc(inline)]
 pub use {
@@ -61,8 +66,13 @@ pub use {
     error::*,
     collection::*,
     document::*,
+    highlight::*,
+    tree::*,
 };
 use snafu::ResultExt;
+use serde::Serialize;
+use std::io::Write;
+use std::collections::{HashMap, HashSet};
 use std::fs::{File, read_dir};
 use std::path::{PathBuf, Path};
 use uuid::Uuid;
@@ -72,16 +82,154 @@ pub struct FileSystemStore {
     pub path: PathBuf,
 }
 
+/// Which items [`Store::all`] returns.
+///
+/// Items are trashed when they, or one of the collections they are in, were moved to the trash.
+/// Deleted items were removed from the trash on the tablet and only wait to be synced.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Filter {
+    #[default]
+    Live,
+    Trashed,
+    Deleted,
+    All,
+}
+
+impl Filter {
+    pub fn apply(self, items: Vec<Item>) -> Vec<Item> {
+        if self == Filter::All {
+            return items;
+        }
+        let parents: HashMap<Uuid, Option<Uuid>> = items.iter().map(|item| (item.id, item.parent)).collect();
+        let trashed = |item: &Item| {
+            let mut parent = item.parent;
+            let mut seen = HashSet::new();
+            while let Some(id) = parent {
+                if id == TRASH {
+                    return true;
+                }
+                if !seen.insert(id) {
+                    break;
+                }
+                parent = parents.get(&id).copied().flatten();
+            }
+            false
+        };
+        items
+            .into_iter()
+            .filter(|item| match self {
+                Filter::Live => !item.deleted && !trashed(item),
+                Filter::Trashed => !item.deleted && trashed(item),
+                Filter::Deleted => item.deleted,
+                Filter::All => true,
+            })
+            .collect()
+    }
+}
+
 pub trait Store {
-    fn all(&self) -> Result<Vec<Item>>;
+    fn all(&self, filter: Filter) -> Result<Vec<Item>>;
     fn by_id(&self, id: &str) -> Result<Item>;
     fn by_path(&self, path: &Path) -> Result<Item>;
     fn load(&self, id: &str) -> Result<ItemType>;
     fn get_file(&self, path: &Path) -> Result<File>;
+
+    /// Nests all items by their parents.
+    fn tree(&self) -> Result<Tree> {
+        let items = self.all(Filter::All)?.into_iter().filter(|item| !item.deleted).collect();
+        Ok(Tree::from_items(items))
+    }
+
+    /// Finds an item by its uuid, or by a slash-separated path of visible names such as `/Work/Papers/foo`.
+    fn resolve(&self, reference: &str) -> Result<Item> {
+        match Uuid::parse_str(reference) {
+            Ok(id) => self.by_id(&id.to_string()),
+            Err(_) => self.by_id(&self.tree()?.resolve_path(reference)?.id.to_string()),
+        }
+    }
+
+    /// Finds a trashed item by its uuid, or by its path of visible names within the trash.
+    fn resolve_trashed(&self, reference: &str) -> Result<Item> {
+        match Uuid::parse_str(reference) {
+            Ok(id) => self.all(Filter::Trashed)?
+                .into_iter()
+                .find(|item| item.id == id)
+                .ok_or(Error::PathNotFound { path: reference.to_string() }),
+            Err(_) => self.by_id(&self.tree()?.resolve_trash_path(reference)?.id.to_string()),
+        }
+    }
+
+    /// Writes the metadata and content of an item.
+    fn save(&self, item: &ItemType) -> Result<()>;
+
+    /// Physically removes an item and all files belonging to it.
+    fn remove(&self, id: &str) -> Result<()>;
+
+    /// Copies a PDF or EPub file into the store, inside the collection `parent`.
+    fn import(&self, path: &Path, parent: Option<Uuid>) -> Result<Item>;
+
+    /// Creates a notebook inside the collection `parent`, with one empty page per template name.
+    /// Fails without any templates, as `xochitl` can't open notebooks without pages.
+    fn create_notebook(&self, name: &str, parent: Option<Uuid>, templates: &[String]) -> Result<Item>;
+
+    /// Applies `change` to the metadata of an item and saves it, marking it as modified.
+    fn update(&self, id: &str, change: &mut dyn FnMut(&mut Item) -> Result<()>) -> Result<Item>;
+
+    fn rename(&self, id: &str, name: &str) -> Result<Item> {
+        self.update(id, &mut |item| {
+            item.visible_name = name.to_string();
+            Ok(())
+        })
+    }
+
+    /// Moves an item into the collection `parent`, or to the top level if it is `None`.
+    fn move_to(&self, id: &str, parent: Option<Uuid>) -> Result<Item> {
+        ensure_collection(self, parent)?;
+        ensure_not_within(self, &self.by_id(id)?.id, parent)?;
+        self.update(id, &mut |item| {
+            item.parent = parent;
+            Ok(())
+        })
+    }
+
+    fn set_pinned(&self, id: &str, pinned: bool) -> Result<Item> {
+        self.update(id, &mut |item| {
+            item.pinned = pinned;
+            Ok(())
+        })
+    }
+
+    fn trash(&self, id: &str) -> Result<Item> {
+        self.update(id, &mut |item| {
+            item.parent = Some(TRASH);
+            Ok(())
+        })
+    }
+
+    /// Moves an item out of the trash, or out of a trashed collection, to the top level.
+    /// Fails for items not in the trash, leaving them unchanged.
+    fn restore(&self, id: &str) -> Result<Item> {
+        if !self.all(Filter::Trashed)?.iter().any(|item| item.id.to_string() == id) {
+            return NotTrashedSnafu { id }.fail();
+        }
+        self.update(id, &mut |item| {
+            item.parent = None;
+            Ok(())
+        })
+    }
+
+    /// Removes all trashed items, including the contents of trashed collections.
+    fn empty_trash(&self) -> Result<Vec<Item>> {
+        let items = self.all(Filter::Trashed)?;
+        for item in &items {
+            self.remove(&item.id.to_string())?;
+        }
+        Ok(items)
+    }
 }
 
 impl Store for FileSystemStore {
-    fn all(&self) -> Result<Vec<Item>> {
+    fn all(&self, filter: Filter) -> Result<Vec<Item>> {
         let mut result = Vec::new();
         let documents =
             read_dir(self.path.as_path()).context(ReadStoreSnafu {
@@ -98,7 +246,7 @@ impl Store for FileSystemStore {
             }
             result.push(Self::by_path(self, &document.path())?)
         }
-        Ok(result)
+        Ok(filter.apply(result))
     }
 
     fn by_id(&self, id: &str) -> Result<Item> {
@@ -127,7 +275,8 @@ impl Store for FileSystemStore {
                 Ok(ItemType::Collection(Box::new(Collection { metadata, content })))
             },
             "DocumentType" => {
-                let content : document::Content = self.from_json_file(path)?;
+                let mut content : document::Content = self.from_json_file(path)?;
+                content.apply_c_pages();
                 Ok(ItemType::Document(Box::new(Document { metadata, content })))
             },
             _ => InvalidItemTypeSnafu { id, type_: metadata.type_ }.fail()
@@ -138,16 +287,197 @@ impl Store for FileSystemStore {
         let path = &self.path.join(path);
         File::open(path).context(ReadFileSnafu {path})
     }
+
+    fn save(&self, item: &ItemType) -> Result<()> {
+        let metadata = match item {
+            ItemType::Collection(c) => &c.metadata,
+            ItemType::Document(d) => &d.metadata,
+        };
+        let path = &Path::new(&metadata.id.to_string()).with_extension("content");
+        match item {
+            ItemType::Collection(c) => self.to_json_file(path, &c.content)?,
+            ItemType::Document(d) => self.to_json_file(path, &d.content)?,
+        }
+        self.to_json_file(&path.with_extension("metadata"), metadata)
+    }
+
+    fn remove(&self, id: &str) -> Result<()> {
+        let base = Path::new(id);
+        for extension in ["content", "pagedata", "pdf", "epub", "epubindex", "local", "bookm"] {
+            remove_path(&self.path.join(base.with_extension(extension)))?;
+        }
+        for directory in ["", "thumbnails", "highlights", "textconversion", "cache"] {
+            remove_path(&self.path.join(base.with_extension(directory)))?;
+        }
+        // copies of the item, `{id}_{n}.zip`
+        let prefix = format!("{}_", id);
+        for file in read_dir(&self.path).context(ReadStoreSnafu { path: &self.path })? {
+            let name = file.context(ReadStoreSnafu { path: &self.path })?.file_name();
+            let is_copy = name.to_str()
+                .and_then(|name| name.strip_prefix(&prefix)?.strip_suffix(".zip"))
+                .is_some_and(|n| n.parse::<usize>().is_ok());
+            if is_copy {
+                remove_path(&self.path.join(name))?;
+            }
+        }
+        // last, so that a partially removed item still shows up
+        remove_path(&self.path.join(base.with_extension("metadata")))
+    }
+
+    fn import(&self, path: &Path, parent: Option<Uuid>) -> Result<Item> {
+        ensure_collection(self, parent)?;
+        let file_type = match path.extension().and_then(|e| e.to_str()).map(str::to_lowercase).as_deref() {
+            Some("pdf") => "pdf",
+            Some("epub") => "epub",
+            _ => return UnsupportedFileSnafu { path }.fail(),
+        };
+        // xochitl paginates epubs itself when opening them
+        let page_count = match file_type {
+            "pdf" => lopdf::Document::load(path).context(ReadPdfSnafu { path })?.get_pages().len(),
+            _ => 0,
+        };
+        let name = path.file_stem().map_or(String::new(), |n| n.to_string_lossy().to_string());
+        let item = Item::new("DocumentType", &name, parent);
+        let mut content = document::Content::new(file_type, page_count);
+
+        let id = item.id.to_string();
+        let target = &self.path.join(&id).with_extension(file_type);
+        let size = std::fs::copy(path, target).context(WriteFileSnafu { path: target })?;
+        content.size_in_bytes = size.to_string();
+        for directory in [self.path.join(&id), self.path.join(&id).with_extension("thumbnails")] {
+            std::fs::create_dir_all(&directory).context(WriteFileSnafu { path: directory })?;
+        }
+        // metadata last, so that xochitl doesn't pick up a half-imported document
+        self.save(&ItemType::Document(Box::new(Document { metadata: item, content })))?;
+        self.by_id(&id)
+    }
+
+    fn create_notebook(&self, name: &str, parent: Option<Uuid>, templates: &[String]) -> Result<Item> {
+        if templates.is_empty() {
+            return EmptyNotebookSnafu { name }.fail();
+        }
+        ensure_collection(self, parent)?;
+        let item = Item::new("DocumentType", name, parent);
+        let content = document::Content::new("notebook", templates.len());
+
+        let id = item.id.to_string();
+        let pages = &self.path.join(&id);
+        std::fs::create_dir_all(pages).context(WriteFileSnafu { path: pages })?;
+        for page in &content.pages {
+            let path = &pages.join(page.to_string()).with_extension("rm");
+            std::fs::write(path, crate::lines::empty_page()).context(WriteFileSnafu { path })?;
+        }
+        let pagedata: String = templates.iter().map(|template| format!("{}\n", template)).collect();
+        let path = &pages.with_extension("pagedata");
+        std::fs::write(path, pagedata).context(WriteFileSnafu { path })?;
+        self.save(&ItemType::Document(Box::new(Document { metadata: item, content })))?;
+        self.by_id(&id)
+    }
+
+    fn update(&self, id: &str, change: &mut dyn FnMut(&mut Item) -> Result<()>) -> Result<Item> {
+        let mut item = self.by_id(id)?;
+        change(&mut item)?;
+        item.version += 1;
+        item.metadatamodified = true;
+        item.modified = true;
+        item.last_modified = crate::utils::timestamp_now();
+        self.to_json_file(&Path::new(id).with_extension("metadata"), &item)?;
+        Ok(item)
+    }
+}
+
+/// JSON objects which remember the keys they were read with.
+///
+/// Writing them back keeps the original order of keys and does not add keys which
+/// the firmware that wrote the file doesn't know. Objects created from scratch are written with all keys.
+pub trait KeyOrder {
+    fn keys(&self) -> &[String];
+    fn set_keys(&mut self, keys: Vec<String>);
 }
 
 impl FileSystemStore {
     pub fn from_json_file<T>(&self, path: &Path) -> Result<T>
-    where T: serde::de::DeserializeOwned
+    where T: serde::de::DeserializeOwned + KeyOrder
     {
         let file = self.get_file(path)?;
-        serde_json::from_reader(file).context(ParseJsonSnafu {
-            path: path.to_path_buf(),
-        })
+        let object: serde_json::Map<String, serde_json::Value> = serde_json::from_reader(file)
+            .context(ParseJsonSnafu { path })?;
+        let keys = object.keys().cloned().collect();
+        let mut value: T = serde_json::from_value(object.into())
+            .context(ParseJsonSnafu { path })?;
+        value.set_keys(keys);
+        Ok(value)
+    }
+
+    pub fn to_json_file<T>(&self, path: &Path, value: &T) -> Result<()>
+    where T: serde::Serialize + KeyOrder
+    {
+        let json = serde_json::to_value(value).context(SerializeJsonSnafu { path })?;
+        self.write_json_file(path, json, value.keys())
+    }
+
+    /// Replaces a file atomically, by writing to a temporary file next to it first.
+    /// Objects are written in the format `xochitl` uses, restricted to and ordered by `keys` if there are any.
+    fn write_json_file(&self, path: &Path, json: serde_json::Value, keys: &[String]) -> Result<()> {
+        let json = match json {
+            serde_json::Value::Object(mut object) if !keys.is_empty() => keys
+                .iter()
+                .filter_map(|key| object.remove(key).map(|value| (key.clone(), value)))
+                .collect::<serde_json::Map<_, _>>()
+                .into(),
+            // like Qt, which sorts the keys of objects
+            serde_json::Value::Object(object) => {
+                let mut entries: Vec<_> = object.into_iter().collect();
+                entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+                entries.into_iter().collect::<serde_json::Map<_, _>>().into()
+            }
+            json => json,
+        };
+
+        let path = &self.path.join(path);
+        let mut file = tempfile::NamedTempFile::new_in(&self.path).context(WriteFileSnafu { path })?;
+        let mut serializer = serde_json::Serializer::with_formatter(&mut file, crate::utils::QtFormatter::default());
+        json.serialize(&mut serializer).context(SerializeJsonSnafu { path })?;
+        file.write_all(b"\n").context(WriteFileSnafu { path })?;
+        file.persist(path).map_err(|e| e.error).context(WriteFileSnafu { path })?;
+        Ok(())
+    }
+}
+
+/// Fails unless `parent` is the top level or an existing collection.
+fn ensure_collection<S: Store + ?Sized>(store: &S, parent: Option<Uuid>) -> Result<()> {
+    if let Some(parent) = parent {
+        let collection = store.by_id(&parent.to_string())?;
+        if collection.type_ != "CollectionType" {
+            return InvalidItemTypeSnafu { id: parent.to_string(), type_: collection.type_ }.fail();
+        }
+    }
+    Ok(())
+}
+
+/// Fails if `parent` is the item `id` or one of the collections within it, as moving it there would create a cycle.
+fn ensure_not_within<S: Store + ?Sized>(store: &S, id: &Uuid, parent: Option<Uuid>) -> Result<()> {
+    let mut visited = HashSet::new();
+    let mut ancestor = parent;
+    while let Some(current) = ancestor.filter(|a| *a != TRASH && visited.insert(*a)) {
+        if current == *id {
+            return MoveIntoItselfSnafu { id: id.to_string(), parent: current }.fail();
+        }
+        ancestor = store.by_id(&current.to_string())?.parent;
+    }
+    Ok(())
+}
+
+/// Removes a file or directory, if it exists.
+fn remove_path(path: &Path) -> Result<()> {
+    let result = if path.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
+    };
+    match result {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        result => result.context(RemoveFileSnafu { path }),
     }
 }
 
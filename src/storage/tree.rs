@@ -0,0 +1,184 @@
+//! The hierarchy of collections in a store, built from the `parent` of each [`Item`].
+
+use super::{error::*, item::{Item, TRASH}};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+/// An item and, if it is a collection, everything in it.
+#[derive(Debug)]
+pub struct Node {
+    pub item: Item,
+    pub children: Vec<Node>,
+}
+
+/// All items of a store, nested by their parents.
+#[derive(Debug, Default)]
+pub struct Tree {
+    /// Items at the top level.
+    pub children: Vec<Node>,
+    /// Items moved to the trash.
+    pub trash: Vec<Node>,
+    /// Items whose parent does not exist anymore, or which are their own ancestors.
+    pub orphans: Vec<Node>,
+}
+
+impl Tree {
+    pub fn from_items(items: Vec<Item>) -> Self {
+        let ids: HashSet<Uuid> = items.iter().map(|item| item.id).collect();
+        let mut by_parent: HashMap<Option<Uuid>, Vec<Item>> = HashMap::new();
+        for item in items {
+            by_parent.entry(item.parent).or_default().push(item);
+        }
+
+        let children = nodes(&mut by_parent, None);
+        let trash = nodes(&mut by_parent, Some(TRASH));
+        let missing: Vec<Option<Uuid>> = by_parent
+            .keys()
+            .filter(|parent| parent.is_some_and(|id| !ids.contains(&id)))
+            .copied()
+            .collect();
+        let mut orphans: Vec<Node> = missing
+            .into_iter()
+            .flat_map(|parent| nodes(&mut by_parent, parent))
+            .collect();
+        // whatever is left is part of a cycle and can't be reached from anywhere
+        while let Some(&parent) = by_parent.keys().next() {
+            orphans.append(&mut nodes(&mut by_parent, parent));
+        }
+        sort(&mut orphans);
+
+        Self { children, trash, orphans }
+    }
+
+    /// Finds the item at a slash-separated path of visible names, such as `/Work/Papers/foo`.
+    pub fn resolve_path(&self, path: &str) -> Result<&Item> {
+        resolve_in(&self.children, path)
+    }
+
+    /// Finds a trashed item by its path within the trash, such as `/Work/Notes` for `Notes` in a trashed `Work`.
+    pub fn resolve_trash_path(&self, path: &str) -> Result<&Item> {
+        resolve_in(&self.trash, path)
+    }
+
+    /// Returns the path of visible names leading to an item, if it is neither trashed nor orphaned.
+    pub fn path_of(&self, id: &Uuid) -> Option<String> {
+        fn find(nodes: &[Node], id: &Uuid, path: &mut Vec<String>) -> bool {
+            for node in nodes {
+                path.push(node.item.visible_name.clone());
+                if node.item.id == *id || find(&node.children, id, path) {
+                    return true;
+                }
+                path.pop();
+            }
+            false
+        }
+
+        let mut path = Vec::new();
+        find(&self.children, id, &mut path).then(|| format!("/{}", path.join("/")))
+    }
+}
+
+/// Finds the item at `path` below `nodes`.
+fn resolve_in<'a>(nodes: &'a [Node], path: &str) -> Result<&'a Item> {
+    let mut children = nodes;
+    let mut found = None;
+    for name in path.split('/').filter(|name| !name.is_empty()) {
+        let matches: Vec<&Node> = children.iter().filter(|node| node.item.visible_name == name).collect();
+        let node = match matches.as_slice() {
+            [] => return PathNotFoundSnafu { path }.fail(),
+            [node] => *node,
+            _ => return AmbiguousPathSnafu { path, ids: matches.iter().map(|node| node.item.id).collect::<Vec<_>>() }.fail(),
+        };
+        children = &node.children;
+        found = Some(&node.item);
+    }
+    found.ok_or(Error::PathNotFound { path: path.to_string() })
+}
+
+/// Removes the items in `parent` from `by_parent` and nests their children below them.
+fn nodes(by_parent: &mut HashMap<Option<Uuid>, Vec<Item>>, parent: Option<Uuid>) -> Vec<Node> {
+    let items = by_parent.remove(&parent).unwrap_or_default();
+    let mut nodes: Vec<Node> = items
+        .into_iter()
+        .map(|item| {
+            let children = nodes(by_parent, Some(item.id));
+            Node { item, children }
+        })
+        .collect();
+    sort(&mut nodes);
+    nodes
+}
+
+/// Collections first, then by name.
+fn sort(nodes: &mut [Node]) {
+    nodes.sort_by(|a, b| {
+        (a.item.type_ != "CollectionType", &a.item.visible_name)
+            .cmp(&(b.item.type_ != "CollectionType", &b.item.visible_name))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(id: u128, parent: &str, type_: &str, name: &str) -> Item {
+        let mut item: Item = serde_json::from_value(serde_json::json!({
+            "type": type_,
+            "lastModified": "0",
+            "parent": parent,
+            "pinned": false,
+            "visibleName": name,
+        })).expect("Invalid test item");
+        item.id = Uuid::from_u128(id);
+        item
+    }
+
+    fn tree() -> Tree {
+        let work = Uuid::from_u128(1).to_string();
+        let papers = Uuid::from_u128(2).to_string();
+        Tree::from_items(vec![
+            item(3, &papers, "DocumentType", "foo"),
+            item(2, &work, "CollectionType", "Papers"),
+            item(1, "", "CollectionType", "Work"),
+            item(4, &work, "DocumentType", "Notes"),
+            item(5, &work, "DocumentType", "Notes"),
+            item(6, "trash", "DocumentType", "Old"),
+            item(7, &Uuid::from_u128(99).to_string(), "DocumentType", "Lost"),
+        ])
+    }
+
+    #[test]
+    fn it_nests_items_by_parent() {
+        let tree = tree();
+        assert_eq!(tree.children.len(), 1);
+        let names: Vec<&str> = tree.children[0].children.iter().map(|n| n.item.visible_name.as_str()).collect();
+        assert_eq!(names, ["Papers", "Notes", "Notes"]);
+        assert_eq!(tree.trash[0].item.visible_name, "Old");
+        assert_eq!(tree.orphans[0].item.visible_name, "Lost");
+    }
+
+    #[test]
+    fn it_resolves_paths() {
+        let tree = tree();
+        assert_eq!(tree.resolve_path("/Work/Papers/foo").map(|i| i.id).ok(), Some(Uuid::from_u128(3)));
+        assert_eq!(tree.path_of(&Uuid::from_u128(3)).as_deref(), Some("/Work/Papers/foo"));
+        assert_eq!(tree.path_of(&Uuid::from_u128(6)), None);
+        assert!(matches!(tree.resolve_path("/Work/Missing"), Err(Error::PathNotFound { .. })));
+        assert!(matches!(tree.resolve_path("/Work/Notes"), Err(Error::AmbiguousPath { ids, .. }) if ids.len() == 2));
+    }
+
+    #[test]
+    fn it_resolves_paths_within_the_trash() {
+        let archive = Uuid::from_u128(1).to_string();
+        let tree = Tree::from_items(vec![
+            item(1, "trash", "CollectionType", "Archive"),
+            item(2, &archive, "DocumentType", "Notes"),
+            item(3, "trash", "DocumentType", "Draft"),
+            item(4, "trash", "DocumentType", "Draft"),
+            item(5, "", "DocumentType", "Notes"),
+        ]);
+        assert_eq!(tree.resolve_trash_path("/Archive/Notes").map(|i| i.id).ok(), Some(Uuid::from_u128(2)));
+        assert!(matches!(tree.resolve_trash_path("/Notes"), Err(Error::PathNotFound { .. })));
+        assert!(matches!(tree.resolve_trash_path("Draft"), Err(Error::AmbiguousPath { ids, .. }) if ids.len() == 2));
+    }
+}
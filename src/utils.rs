@@ -27,3 +27,146 @@ where
         Some(s) => Uuid::deserialize(s.into_deserializer()).map(Some),
     }
 }
+
+pub fn serialize_parent<S>(parent: &Option<Uuid>, se: S) -> core::result::Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    match parent {
+        None => se.serialize_str(""),
+        Some(id) if id.is_nil() => se.serialize_str("trash"),
+        Some(id) => id.serialize(se),
+    }
+}
+
+/// Writes JSON like Qt´s `QJsonDocument::Indented`, which `xochitl` uses:
+/// indented by four spaces, with empty arrays and objects spanning two lines.
+#[derive(Debug, Default)]
+pub struct QtFormatter {
+    indent: usize,
+    has_value: bool,
+}
+
+impl QtFormatter {
+    fn begin<W: ?Sized + std::io::Write>(&mut self, writer: &mut W, bracket: &[u8]) -> std::io::Result<()> {
+        self.indent += 1;
+        self.has_value = false;
+        writer.write_all(bracket)?;
+        writer.write_all(b"\n")
+    }
+
+    fn end<W: ?Sized + std::io::Write>(&mut self, writer: &mut W, bracket: &[u8]) -> std::io::Result<()> {
+        self.indent -= 1;
+        if self.has_value {
+            writer.write_all(b"\n")?;
+        }
+        self.has_value = true;
+        self.write_indent(writer)?;
+        writer.write_all(bracket)
+    }
+
+    fn begin_value<W: ?Sized + std::io::Write>(&mut self, writer: &mut W, first: bool) -> std::io::Result<()> {
+        if !first {
+            writer.write_all(b",\n")?;
+        }
+        self.write_indent(writer)
+    }
+
+    fn write_indent<W: ?Sized + std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        for _ in 0..self.indent {
+            writer.write_all(b"    ")?;
+        }
+        Ok(())
+    }
+}
+
+impl serde_json::ser::Formatter for QtFormatter {
+    fn begin_array<W: ?Sized + std::io::Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.begin(writer, b"[")
+    }
+
+    fn end_array<W: ?Sized + std::io::Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.end(writer, b"]")
+    }
+
+    fn begin_array_value<W: ?Sized + std::io::Write>(&mut self, writer: &mut W, first: bool) -> std::io::Result<()> {
+        self.begin_value(writer, first)
+    }
+
+    fn end_array_value<W: ?Sized + std::io::Write>(&mut self, _writer: &mut W) -> std::io::Result<()> {
+        self.has_value = true;
+        Ok(())
+    }
+
+    fn begin_object<W: ?Sized + std::io::Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.begin(writer, b"{")
+    }
+
+    fn end_object<W: ?Sized + std::io::Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.end(writer, b"}")
+    }
+
+    fn begin_object_key<W: ?Sized + std::io::Write>(&mut self, writer: &mut W, first: bool) -> std::io::Result<()> {
+        self.begin_value(writer, first)
+    }
+
+    fn begin_object_value<W: ?Sized + std::io::Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        writer.write_all(b": ")
+    }
+
+    fn end_object_value<W: ?Sized + std::io::Write>(&mut self, _writer: &mut W) -> std::io::Result<()> {
+        self.has_value = true;
+        Ok(())
+    }
+
+    /// Qt writes doubles without a fraction as integers, such as `"customZoomCenterY": 936`.
+    fn write_f64<W: ?Sized + std::io::Write>(&mut self, writer: &mut W, value: f64) -> std::io::Result<()> {
+        if value.fract() == 0. && value.abs() < 1e15 {
+            write!(writer, "{}", value as i64)
+        } else {
+            serde_json::ser::Formatter::write_f64(&mut serde_json::ser::CompactFormatter, writer, value)
+        }
+    }
+}
+
+/// Milliseconds since the epoch as a string, like `xochitl` stores timestamps.
+pub fn timestamp_now() -> String {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis())
+        .to_string()
+}
+
+/// Formats a timestamp in milliseconds since the epoch, as used by `xochitl`, as an RFC 3339 date in UTC.
+pub fn format_timestamp(millis: &str) -> Option<String> {
+    let seconds = millis.parse::<i64>().ok()?.div_euclid(1000);
+    let (days, time) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    Some(format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, time / 3600, time % 3600 / 60, time % 60
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_formats_timestamps() {
+        assert_eq!(format_timestamp("1660000000000").as_deref(), Some("2022-08-08T23:06:40Z"));
+        assert_eq!(format_timestamp("0").as_deref(), Some("1970-01-01T00:00:00Z"));
+        assert_eq!(format_timestamp("not a number"), None);
+    }
+}